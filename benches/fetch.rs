@@ -0,0 +1,134 @@
+//! Throughput of `get_anilist_scores` for a 1000-title list against a local mock of
+//! Anilist, at several chunk sizes and with several users fetched at once. Each run sends
+//! the list query and one average score query per chunk over HTTP, so this measures the
+//! round trips along with building the queries and assembling their responses.
+use avg_anilist_index::{get_anilist_scores, max_chunk_size, QueryOptions};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const TITLES: i64 = 1000;
+
+/// Response to a list query: one completed list of `TITLES` scored entries.
+fn list_response() -> Value {
+    let entries: Vec<Value> = (1..=TITLES)
+        .map(|id| json!({"mediaId": id, "score": id % 10 + 1, "updatedAt": 0, "status": "COMPLETED"}))
+        .collect();
+    json!({"data": {"MediaListCollection": {"lists": [
+        {"name": "Completed", "isCustomList": false, "entries": entries}
+    ]}}})
+}
+
+/// Response to an average score query, one aliased `Media` per `id_N` variable.
+fn chunk_response(variables: &Value) -> Value {
+    let fields: Map<String, Value> = (0..)
+        .map_while(|i| Some((i, variables.get(format!("id_{i}"))?.as_i64()?)))
+        .map(|(i, id)| {
+            (
+                format!("query_{i}"),
+                json!({
+                    "averageScore": id % 100,
+                    "genres": ["Drama"],
+                    "status": "FINISHED",
+                    "seasonYear": 2010,
+                    "title": {"romaji": format!("Title {id}"), "english": null, "native": null}
+                }),
+            )
+        })
+        .collect();
+    json!({ "data": fields })
+}
+
+/// Answer every request on `stream` until the client closes it, keeping it alive between
+/// requests as Anilist does.
+fn serve(stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                content_length = len.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let query: Value = serde_json::from_slice(&body).unwrap();
+        let response = if query["query"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("MediaListCollection")
+        {
+            list_response()
+        } else {
+            chunk_response(&query["variables"])
+        }
+        .to_string();
+        let written = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{response}",
+            response.len()
+        );
+        if written.is_err() {
+            return;
+        }
+    }
+}
+
+/// Start the mock server, returning its URL.
+fn mock_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve(stream));
+        }
+    });
+    url
+}
+
+fn fetch(c: &mut Criterion) {
+    let url = mock_server();
+    let mut group = c.benchmark_group("fetch_1000_titles");
+    group.sample_size(10);
+
+    for concurrency in [1, 4] {
+        group.throughput(Throughput::Elements(TITLES as u64 * concurrency));
+        for chunk_size in [10, 25, max_chunk_size(false)] {
+            let options = QueryOptions {
+                chunk_size: Some(chunk_size),
+                endpoints: vec![url.clone()],
+                ..Default::default()
+            };
+            group.bench_with_input(
+                BenchmarkId::new(format!("{concurrency}_users"), chunk_size),
+                &options,
+                |b, options| {
+                    b.iter(|| {
+                        thread::scope(|scope| {
+                            for _ in 0..concurrency {
+                                scope.spawn(|| {
+                                    black_box(get_anilist_scores("bench", "ANIME", options))
+                                        .unwrap()
+                                });
+                            }
+                        })
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fetch);
+criterion_main!(benches);
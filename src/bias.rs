@@ -0,0 +1,184 @@
+use polars::prelude::{df, DataFrame, NamedFrom, Series};
+use std::collections::BTreeMap;
+
+use crate::AnilistScores;
+
+/// Tag rank from which `--by-tag` counts a tag, unless `--tag-min-rank` overrides it.
+pub const DEFAULT_TAG_MIN_RANK: i64 = 60;
+/// Tags listed by `--by-tag` as the ones the user is most contrarian about.
+pub const CONTRARIAN_TAGS: usize = 5;
+
+/// How far the user scores titles of a genre or tag from their global score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bias {
+    /// Name of the genre or tag.
+    pub name: String,
+    pub titles: usize,
+    /// Mean user score on the 100-point scale.
+    pub mean_user_score: f64,
+    pub mean_global_score: f64,
+    /// Mean of `user_score - global_avg_score`. Positive if the user rates it higher.
+    pub bias: f64,
+    /// Standard deviation of the differences averaged by `bias`.
+    pub bias_std: f64,
+}
+
+/// Bias of every label `labels` gives the scored titles in `lists`, most favoured first. A
+/// title counts toward each of its labels, once even if it is on several lists. Titles
+/// without a user or global score, or without labels, are skipped.
+fn label_bias<'a>(
+    lists: &'a [AnilistScores],
+    labels: impl Fn(&'a AnilistScores, usize) -> Option<Vec<&'a str>>,
+) -> Vec<Bias> {
+    let mut seen = BTreeMap::new();
+    for list in lists {
+        let scale = list.user_score_scale();
+        for (i, id) in list.anilist_id.iter().enumerate() {
+            let (user, global) = (list.user_score[i], list.global_avg_score[i]);
+            let Some(names) = labels(list, i) else {
+                continue;
+            };
+            if user != 0 && global != 0 {
+                seen.entry(*id)
+                    .or_insert(((user * scale) as f64, global as f64, names));
+            }
+        }
+    }
+
+    let mut by_label: BTreeMap<&str, Vec<(f64, f64)>> = BTreeMap::new();
+    for (user, global, names) in seen.values() {
+        for name in names.iter() {
+            by_label.entry(name).or_default().push((*user, *global));
+        }
+    }
+
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let mut biases: Vec<Bias> = by_label
+        .into_iter()
+        .map(|(name, pairs)| {
+            let diffs: Vec<f64> = pairs.iter().map(|(user, global)| user - global).collect();
+            let bias = mean(&diffs);
+            let variance = mean(
+                &diffs
+                    .iter()
+                    .map(|diff| (diff - bias).powi(2))
+                    .collect::<Vec<f64>>(),
+            );
+            Bias {
+                name: name.to_string(),
+                titles: pairs.len(),
+                mean_user_score: mean(&pairs.iter().map(|(user, _)| *user).collect::<Vec<_>>()),
+                mean_global_score: mean(
+                    &pairs.iter().map(|(_, global)| *global).collect::<Vec<_>>(),
+                ),
+                bias,
+                bias_std: variance.sqrt(),
+            }
+        })
+        .collect();
+    biases.sort_by(|a, b| b.bias.total_cmp(&a.bias));
+    biases
+}
+
+/// Bias of every genre of the scored titles in `lists`, most favoured first.
+pub fn genre_bias(lists: &[AnilistScores]) -> Vec<Bias> {
+    label_bias(lists, |list, i| {
+        list.genres
+            .get(i)
+            .map(|genres| genres.iter().map(|genre| genre.as_str()).collect())
+    })
+}
+
+/// Bias of every tag ranked at least `min_rank` on the scored titles in `lists`, most
+/// favoured first.
+pub fn tag_bias(lists: &[AnilistScores], min_rank: i64) -> Vec<Bias> {
+    label_bias(lists, |list, i| {
+        list.tags.get(i).map(|tags| {
+            tags.iter()
+                .filter(|(_, rank)| *rank >= min_rank)
+                .map(|(name, _)| name.as_str())
+                .collect()
+        })
+    })
+}
+
+/// Up to `n` of `biases` furthest from 0 in either direction, furthest first.
+pub fn most_contrarian(biases: &[Bias], n: usize) -> Vec<Bias> {
+    let mut biases = biases.to_vec();
+    biases.sort_by(|a, b| b.bias.abs().total_cmp(&a.bias.abs()));
+    biases.truncate(n);
+    biases
+}
+
+/// One row per genre or tag, its name in the `label` column.
+pub fn bias_dataframe(biases: &[Bias], label: &str) -> Result<DataFrame, String> {
+    let df = df!(
+        label => biases.iter().map(|bias| bias.name.clone()).collect::<Vec<String>>(),
+        "titles" => biases.iter().map(|bias| bias.titles as i64).collect::<Vec<i64>>(),
+        "mean_user_score" => biases.iter().map(|bias| bias.mean_user_score).collect::<Vec<f64>>(),
+        "mean_global_score" => biases.iter().map(|bias| bias.mean_global_score).collect::<Vec<f64>>(),
+        "bias" => biases.iter().map(|bias| bias.bias).collect::<Vec<f64>>(),
+        "bias_std" => biases.iter().map(|bias| bias.bias_std).collect::<Vec<f64>>()
+    );
+    df.map_err(|err| format!("Unable to save {label} bias to dataframe: {:?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genres(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn bias_is_grouped_by_genre() {
+        let list = AnilistScores {
+            genres: vec![
+                genres(&["Horror", "Mystery"]),
+                genres(&["Horror"]),
+                genres(&["Comedy"]),
+            ],
+            ..AnilistScores::fixture(vec![90, 60, 0], vec![70, 70, 80])
+        };
+        // The same title on a second list is only counted once.
+        let repeated = AnilistScores {
+            genres: vec![genres(&["Horror", "Mystery"])],
+            ..AnilistScores::fixture(vec![90], vec![70])
+        };
+
+        let biases = genre_bias(&[list, repeated]);
+        assert_eq!(biases.len(), 2);
+        assert_eq!(biases[0].name, "Mystery");
+        assert_eq!(biases[0].bias, 20.0);
+        assert_eq!(biases[1].name, "Horror");
+        assert_eq!(biases[1].titles, 2);
+        assert_eq!(biases[1].bias, 5.0);
+        assert_eq!(biases[1].bias_std, 15.0);
+    }
+
+    #[test]
+    fn titles_without_genres_are_skipped() {
+        let list = AnilistScores::fixture(vec![80], vec![70]);
+
+        assert!(genre_bias(&[list]).is_empty());
+    }
+
+    #[test]
+    fn low_ranked_tags_are_ignored() {
+        let tag = |name: &str, rank: i64| (name.to_string(), rank);
+        let list = AnilistScores {
+            tags: vec![
+                vec![tag("Iyashikei", 95), tag("Travel", 30)],
+                vec![tag("Travel", 80)],
+            ],
+            ..AnilistScores::fixture(vec![90, 40], vec![70, 80])
+        };
+
+        let biases = tag_bias(&[list], DEFAULT_TAG_MIN_RANK);
+        assert_eq!(biases.len(), 2);
+        assert_eq!(biases[1].name, "Travel");
+        assert_eq!(biases[1].titles, 1);
+        assert_eq!(most_contrarian(&biases, 1)[0].name, "Travel");
+    }
+}
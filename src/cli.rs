@@ -0,0 +1,753 @@
+use clap::{Args, Parser, Subcommand};
+
+use std::fs;
+use std::time::Duration;
+
+use crate::bias::DEFAULT_TAG_MIN_RANK;
+use crate::client::{AnilistClient, RetryPolicy, DEFAULT_RETRY_JITTER};
+use crate::error::ErrorFormat;
+use crate::media::TitleLanguage;
+use crate::writer::{parse_columns_order, DisplayScale, OutputFormat, QuoteStyle};
+use crate::{
+    parse_statuses, InvalidScore, Metric, RatioBasis, ScoreBasis, ScoreFormat, TrendBucket,
+    DEFAULT_PRECISION, DEFAULT_WARN_THRESHOLD,
+};
+
+/// Compare the scores of an Anilist profile against the global average scores.
+#[derive(Debug, Parser)]
+#[command(name = "avg_anilist_index", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fetch a user's lists, write them to files and summarize how average their scores
+    /// are. Used when no command is given.
+    Fetch(Box<FetchArgs>),
+    /// Compare the scores of two users on the titles both have scored.
+    Compare(CompareArgs),
+    /// Compare two runs saved with --format json and write the added, removed and
+    /// rescored titles to a changelog CSV. Does not query Anilist.
+    Diff(DiffArgs),
+    /// Confirm Anilist is reachable and the token is accepted, and print the rate limit
+    /// left. Fetches no lists.
+    Check(CheckArgs),
+    /// Read media ids from stdin and print the global score of each as a json line with
+    /// anilist_id and global_avg_score. Input lines are a bare id or a json object with an
+    /// anilist_id, mediaId or id field. Does not fetch any lists.
+    Rescore(RescoreArgs),
+}
+
+/// Names of the subcommands, so arguments without one can default to `fetch`.
+const COMMANDS: &[&str] = &["fetch", "compare", "diff", "check", "rescore", "help"];
+
+/// `args` with `fetch` inserted when no command is given, so `<username> <MEDIA>` keeps
+/// working as before commands existed.
+pub fn with_default_command(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args: Vec<String> = args.into_iter().collect();
+    let needs_command = match args.get(1).map(|arg| arg.as_str()) {
+        None => false,
+        Some("-h" | "--help" | "-V" | "--version") => false,
+        Some(arg) => !COMMANDS.contains(&arg),
+    };
+    if needs_command {
+        args.insert(1, "fetch".to_string());
+    }
+    args
+}
+
+/// A `--precision`, at most the 15 decimal places an f64 reliably holds.
+fn parse_precision(value: &str) -> Result<usize, &'static str> {
+    value
+        .parse()
+        .ok()
+        .filter(|places| *places <= 15)
+        .ok_or("must be a number of decimal places from 0 to 15")
+}
+
+/// Options of every command that queries Anilist.
+#[derive(Debug, Args)]
+pub struct NetworkArgs {
+    /// User-Agent sent to Anilist. [default: AnilistScores/<version>]
+    #[arg(long, value_name = "UA")]
+    pub user_agent: Option<String>,
+    /// Comma-separated GraphQL endpoints, e.g. a mirror or caching proxy, tried in order
+    /// when one can't be reached. [default: https://graphql.anilist.co/]
+    #[arg(long, value_name = "URLS", value_delimiter = ',')]
+    pub endpoint: Vec<String>,
+    /// Upper bound of the random delay added to rate limit retries.
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_RETRY_JITTER.as_millis() as u64)]
+    pub retry_jitter_ms: u64,
+    /// Idle connections kept open per host for reuse. [default: unbounded]
+    ///
+    /// There is no --concurrency: queries are sent one at a time, each reusing the same
+    /// connection, so one is enough today. Raise it together with any concurrent fetching.
+    #[arg(long, value_name = "N")]
+    pub pool_size: Option<usize>,
+}
+
+impl NetworkArgs {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            jitter: Duration::from_millis(self.retry_jitter_ms),
+            ..Default::default()
+        }
+    }
+
+    pub fn client(&self) -> AnilistClient {
+        AnilistClient::new(self.user_agent.as_deref(), self.retry_policy())
+            .with_endpoints(&self.endpoint)
+            .with_pool_size(self.pool_size)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct FetchArgs {
+    pub username: String,
+    /// ANIME or MANGA.
+    pub media: String,
+    /// Output file format: csv, tsv, json, markdown or html. Markdown holds a summary and
+    /// the most divergent titles of each list rather than every entry. Html is a
+    /// self-contained page per list with a chart of your score distribution.
+    #[arg(
+        long,
+        default_value = "csv",
+        value_parser = |value: &str| OutputFormat::from_flag(value)
+            .ok_or("must be one of csv/tsv/json/markdown/html")
+    )]
+    pub format: OutputFormat,
+    /// Drop output columns whose values are all zero or null.
+    #[arg(long)]
+    pub compact: bool,
+    /// Name used for the title column, falling back to the others when missing.
+    #[arg(
+        long,
+        value_name = "romaji|english|native",
+        default_value = "romaji",
+        value_parser = |value: &str| TitleLanguage::from_flag(value)
+            .ok_or("must be one of romaji/english/native")
+    )]
+    pub title_lang: TitleLanguage,
+    /// Comma-separated columns to put first, e.g. title,user_score. The rest follow in
+    /// default order.
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        value_parser = |value: &str| parse_columns_order(value).map(|_| value.to_string())
+    )]
+    pub columns_order: Option<String>,
+    /// Add a notes column with the notes of each entry.
+    #[arg(long)]
+    pub include_notes: bool,
+    /// Add progress and completion_pct columns with the episodes or chapters of each
+    /// entry done so far. completion_pct is empty for ongoing series of unknown length.
+    #[arg(long)]
+    pub include_progress: bool,
+    /// Add an airing_status column with whether each title is FINISHED, RELEASING,
+    /// NOT_YET_RELEASED, CANCELLED or on HIATUS. Averages of releasing titles may still
+    /// change.
+    #[arg(long)]
+    pub include_airing_status: bool,
+    /// Leave out titles that are not yet released, whose global scores are missing or
+    /// based on few early ratings, from the output and the ratio.
+    #[arg(long)]
+    pub exclude_unreleased: bool,
+    /// Add a url column linking to each title's Anilist page.
+    #[arg(long)]
+    pub include_url: bool,
+    /// Quote every csv/tsv field, or only those containing a delimiter, quote or newline.
+    #[arg(
+        long,
+        value_name = "always|necessary",
+        default_value = "necessary",
+        value_parser = |value: &str| QuoteStyle::from_flag(value)
+            .ok_or("must be one of always/necessary")
+    )]
+    pub quote_style: QuoteStyle,
+    /// Write user_score and global_avg_score out of 10, with one decimal, or out of 100.
+    /// Ratios are unaffected.
+    #[arg(
+        long,
+        value_name = "10|100",
+        default_value = "100",
+        value_parser = |value: &str| DisplayScale::from_flag(value)
+            .ok_or("must be one of 10/100")
+    )]
+    pub display_scale: DisplayScale,
+    /// Round user_score and global_avg_score to the nearest integer once on
+    /// --display-scale. Only affects display: ratios and deltas use the precise scores.
+    #[arg(long)]
+    pub round_scores: bool,
+    /// Start csv/tsv output with a UTF-8 byte order mark for Excel.
+    #[arg(long)]
+    pub utf8_bom: bool,
+    /// Write scores to stdout instead of files, one block per list. Summaries are printed
+    /// to stderr.
+    #[arg(long)]
+    pub stdout: bool,
+    /// Print each scored entry to stdout as a json line as soon as its global score is
+    /// fetched, instead of writing files and summaries. --sample and --incremental are
+    /// ignored.
+    #[arg(long, conflicts_with_all = ["stdout", "from_export", "no_global"])]
+    pub stream: bool,
+    /// Directory output files are written to, created if missing.
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub output_dir: String,
+    /// Write output files into an anime/ or manga/ subdirectory of the output directory.
+    #[arg(long)]
+    pub group_by_media: bool,
+    /// Never overwrite output files. Existing names get a numeric suffix instead, e.g.
+    /// name-1.csv.
+    #[arg(long, overrides_with = "overwrite")]
+    pub no_clobber: bool,
+    /// Overwrite existing output files, the default. Whichever of --overwrite and
+    /// --no-clobber comes last wins, so this undoes a --no-clobber set by an alias.
+    #[arg(long, overrides_with = "no_clobber")]
+    pub overwrite: bool,
+    /// Append rows to existing csv/tsv output files instead of overwriting them, so
+    /// repeated runs build up one file per list. The header is only written to new or
+    /// empty files.
+    #[arg(long, conflicts_with_all = ["no_clobber", "overwrite", "stdout"])]
+    pub append: bool,
+    /// Also write every entry to the SQLite database at PATH, created if missing. Each run
+    /// replaces the titles' rows in its `scores` table and adds to `score_history`.
+    #[arg(long, value_name = "PATH")]
+    pub sqlite: Option<String>,
+    /// Also write anilist_<MEDIA>_summary_<username>.csv with one row per list.
+    #[arg(long)]
+    pub summary_csv: bool,
+    /// Print the name and dtype of each output column with the given options, then exit
+    /// without fetching. Columns --compact would drop are included.
+    #[arg(long)]
+    pub print_schema: bool,
+    /// Print the name and entry count of each of the user's lists, then exit without
+    /// fetching scores or writing files.
+    #[arg(long, conflicts_with_all = ["stream", "from_export"])]
+    pub list_lists: bool,
+    /// Also write genre_bias.csv with, per genre, how far your scores are from the global
+    /// scores. Needs global scores.
+    #[arg(long)]
+    pub genre_bias: bool,
+    /// Also fetch each title's tags, write tag_bias.csv with how far your scores are from
+    /// the global scores per tag, and print the tags you are most contrarian about.
+    #[arg(long)]
+    pub by_tag: bool,
+    /// Lowest rank, 0 to 100, of the tags --by-tag counts for a title.
+    #[arg(
+        long,
+        value_name = "RANK",
+        default_value_t = DEFAULT_TAG_MIN_RANK,
+        value_parser = clap::value_parser!(i64).range(0..=100)
+    )]
+    pub tag_min_rank: i64,
+    /// Re-issue the list query up to N times if it returns no lists.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retry_on_empty: u32,
+    /// Keep the first N entries of each list, in the order Anilist returns them. This is
+    /// not a random sample.
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+    /// Abort before fetching any global scores if your lists hold more than N entries,
+    /// instead of truncating them like --limit. Unlimited by default.
+    #[arg(long, value_name = "N")]
+    pub max_entries: Option<usize>,
+    /// Abort with a runtime_exceeded error if fetching takes longer than N seconds in
+    /// total. With --stream, entries printed so far are kept.
+    #[arg(long, value_name = "N")]
+    pub max_runtime_secs: Option<u64>,
+    /// Randomly sample N entries of each list.
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+    /// Seed for --sample so runs are reproducible. Read from ANILIST_SEED if not given,
+    /// e.g. to set it once for a CI pipeline.
+    #[arg(long, value_name = "S", env = "ANILIST_SEED")]
+    pub seed: Option<u64>,
+    /// Comma-separated statuses to keep, e.g. COMPLETED,CURRENT. Picks entries by status
+    /// rather than by list name, so renamed lists still match. One of CURRENT, PLANNING,
+    /// COMPLETED, DROPPED, PAUSED, REPEATING.
+    #[arg(
+        long,
+        value_name = "STATUSES",
+        value_parser = |value: &str| parse_statuses(value).map(|_| value.to_string())
+    )]
+    pub status: Option<String>,
+    /// Also fetch the custom list NAME, if visible. Without a token only public custom
+    /// lists are, and private ones are skipped. Repeat for several lists.
+    #[arg(long, value_name = "NAME")]
+    pub custom_list: Vec<String>,
+    /// Comma-separated lists to leave out, e.g. Planning,Dropped. Applied after the lists
+    /// are picked, ignoring case.
+    #[arg(long, value_name = "LISTS")]
+    pub exclude_lists: Option<String>,
+    /// Warn when fewer than this share of the entries your statistics count were fetched.
+    /// 0 disables it.
+    #[arg(
+        long,
+        value_name = "R",
+        default_value_t = DEFAULT_WARN_THRESHOLD,
+        value_parser = |value: &str| value
+            .parse::<f64>()
+            .ok()
+            .filter(|share| (0.0..=1.0).contains(share))
+            .ok_or("must be a share from 0 to 1")
+    )]
+    pub warn_threshold: f64,
+    /// Scale you score on, for when it can't be detected from your scores. User scores are
+    /// converted to POINT_100. One of POINT_100, POINT_10, POINT_10_DECIMAL, POINT_5,
+    /// POINT_3.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = |value: &str| ScoreFormat::from_flag(value)
+            .ok_or("must be one of POINT_100/POINT_10/POINT_10_DECIMAL/POINT_5/POINT_3")
+    )]
+    pub score_format: Option<ScoreFormat>,
+    /// What to do with scores outside 0 to 100 once on the 100-point scale, e.g. 110 from
+    /// a manual edit: clamp them to the scale, skip their entries, or keep them. A warning
+    /// names them either way.
+    #[arg(
+        long,
+        value_name = "clamp|skip|keep",
+        default_value = "keep",
+        value_parser = |value: &str| InvalidScore::from_flag(value)
+            .ok_or("must be one of clamp/skip/keep")
+    )]
+    pub on_invalid_score: InvalidScore,
+    /// Drop entries hidden from other users.
+    #[arg(long)]
+    pub exclude_private: bool,
+    /// Ids per average score query. Reduced with a warning if the query would exceed
+    /// Anilist's complexity limit.
+    #[arg(long, value_name = "N")]
+    pub chunk_size: Option<usize>,
+    /// Reuse global scores from the previous --format json output for titles not updated
+    /// since, only fetching the rest. Scores are read back on --display-scale, so it must
+    /// match the previous run's.
+    #[arg(long, conflicts_with = "round_scores")]
+    pub incremental: bool,
+    /// Take global scores only from the previous --format json output, never querying
+    /// them, and fail listing the ids of titles it lacks. With --from-export, runs fully
+    /// offline.
+    #[arg(long, requires = "incremental")]
+    pub cache_only: bool,
+    /// Compare the overall average-ness to the previous run's and print whether you became
+    /// more or less contrarian. Saved to anilist_<MEDIA>_ratio_<username>.json in the
+    /// output directory.
+    #[arg(long)]
+    pub track_ratio: bool,
+    /// Read your scores from a list export (.xml or .json) instead of querying Anilist.
+    /// Implies --no-global.
+    #[arg(long, value_name = "FILE")]
+    pub from_export: Option<String>,
+    /// Only export your own scores. Skips fetching global scores and the comparisons that
+    /// need them.
+    #[arg(long)]
+    pub no_global: bool,
+    /// Global score to compare against. average: Anilist's averageScore, weighted toward
+    /// titles with more votes. mean: Anilist's meanScore, the plain mean of all scores.
+    #[arg(
+        long,
+        value_name = "average|mean",
+        default_value = "average",
+        value_parser = |value: &str| ScoreBasis::from_flag(value)
+            .ok_or("must be one of mean/average")
+    )]
+    pub mean_basis: ScoreBasis,
+    /// How the average-ness score of a list is computed. aggregate: summed user scores
+    /// over summed global scores, so every point counts the same. mean: the mean of each
+    /// title's user score over its global score, so every title counts the same and those
+    /// with low global scores sway it more.
+    #[arg(
+        long,
+        value_name = "aggregate|mean",
+        default_value = "aggregate",
+        value_parser = |value: &str| RatioBasis::from_flag(value)
+            .ok_or("must be one of aggregate/mean")
+    )]
+    pub ratio_basis: RatioBasis,
+    /// Summarize scores as a ratio or as the mean of user_score - global_avg_score. delta
+    /// also adds a delta output column.
+    #[arg(
+        long,
+        value_name = "ratio|delta",
+        default_value = "ratio",
+        value_parser = |value: &str| Metric::from_flag(value)
+            .ok_or("must be one of ratio/delta")
+    )]
+    pub metric: Metric,
+    /// Compare the global scores of your favourites against your scored lists.
+    #[arg(long)]
+    pub include_favourites: bool,
+    /// Also print the global scores of your planning list and its highest rated titles.
+    /// Not compared against your scores.
+    #[arg(long)]
+    pub planning_report: bool,
+    /// Also fetch the scored lists of the other media type and print which of anime and
+    /// manga you are more contrarian about. Its scores are not written.
+    #[arg(long, conflicts_with_all = ["from_export", "no_global", "stream"])]
+    pub compare_media: bool,
+    /// Also fetch the dropped and rewatching lists and print how many titles you completed
+    /// once, rewatched, dropped or are watching, with the mean score delta of each.
+    #[arg(long)]
+    pub include_status_changes: bool,
+    /// Also write the status changes report to anilist_<MEDIA>_status_changes_<username>.csv.
+    #[arg(long, requires = "include_status_changes")]
+    pub status_changes_csv: bool,
+    /// Print list stats and the most divergent titles as tables instead of plain lines.
+    #[arg(long)]
+    pub pretty: bool,
+    /// Decimal places of printed ratios, means and correlations, up to 15.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_PRECISION,
+        value_parser = parse_precision
+    )]
+    pub precision: usize,
+    /// Also print the average-ness without the titles whose score deltas are in the top and
+    /// bottom P percent, so a few outliers can't skew it.
+    #[arg(
+        long,
+        value_name = "P",
+        value_parser = |value: &str| value
+            .parse::<f64>()
+            .ok()
+            .filter(|percent| (0.0..50.0).contains(percent))
+            .ok_or("must be a percentage from 0 up to 50")
+    )]
+    pub trim: Option<f64>,
+    /// Also print the average-ness, or mean delta, against global scores min-max rescaled
+    /// onto the range of your own scores: the lowest global score of a list becomes your
+    /// lowest score and the highest your highest, linearly in between. If you only score
+    /// 6-10, titles are then compared by where the crowd ranks them rather than by their
+    /// 0-100 average. Output files are unchanged.
+    #[arg(long)]
+    pub normalize_global: bool,
+    /// Also print the average-ness with each title weighted by how recently it was updated,
+    /// its weight halving every D days, so it reflects current taste.
+    #[arg(
+        long,
+        value_name = "D",
+        value_parser = |value: &str| value
+            .parse::<f64>()
+            .ok()
+            .filter(|days| *days > 0.0)
+            .ok_or("must be a positive number of days")
+    )]
+    pub recency_halflife_days: Option<f64>,
+    /// Print average-ness by the year titles were released.
+    #[arg(long)]
+    pub by_year: bool,
+    /// Print average-ness by when entries were last updated.
+    #[arg(
+        long,
+        value_name = "year|month",
+        value_parser = |value: &str| TrendBucket::from_flag(value)
+            .ok_or("must be one of year/month")
+    )]
+    pub trend: Option<TrendBucket>,
+    /// Print how long the list and average score fetches took, and how many requests were
+    /// sent.
+    #[arg(long)]
+    pub timing: bool,
+    /// Print how many requests were sent, and the user's overall statistics for the media
+    /// type.
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Print nothing but warnings and errors, e.g. for cron. Output files are still
+    /// written.
+    #[arg(short, long, conflicts_with_all = ["verbose", "timing"])]
+    pub quiet: bool,
+    /// Format of errors printed to stderr.
+    #[arg(
+        long,
+        value_name = "text|json",
+        default_value = "text",
+        value_parser = |value: &str| ErrorFormat::from_flag(value)
+            .ok_or("must be one of text/json")
+    )]
+    pub error_format: ErrorFormat,
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    pub username: String,
+    /// User to compare against.
+    pub other: String,
+    /// ANIME or MANGA.
+    pub media: String,
+    /// Decimal places of printed means and correlations, up to 15.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_PRECISION,
+        value_parser = parse_precision
+    )]
+    pub precision: usize,
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    pub old: String,
+    pub new: String,
+    /// Changelog CSV to write.
+    #[arg(long, value_name = "FILE", default_value = "score_changes.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Token to check. Read from ANILIST_TOKEN if not given. Prefer --token-file or
+    /// ANILIST_TOKEN, as flags end up in shell history and process listings.
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Read the token to check from FILE, ignoring surrounding whitespace. Takes
+    /// precedence over ANILIST_TOKEN.
+    #[arg(long, value_name = "FILE", conflicts_with = "token")]
+    pub token_file: Option<String>,
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+impl CheckArgs {
+    /// Token of `--token-file`, or else `--token`, or else `env_token`, the value of
+    /// ANILIST_TOKEN. The env var is read outside clap so it never conflicts with
+    /// `--token-file`.
+    pub fn token(&self, env_token: Option<String>) -> Result<Option<String>, String> {
+        let Some(path) = &self.token_file else {
+            return Ok(self.token.clone().or(env_token));
+        };
+        let token = fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read token file {path}: {err}"))?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(format!("Token file {path} is empty."));
+        }
+        Ok(Some(token.to_string()))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RescoreArgs {
+    /// Media type of the ids: ANIME or MANGA.
+    #[arg(long, default_value = "ANIME")]
+    pub media: String,
+    /// Global score to fetch: average or mean.
+    #[arg(
+        long,
+        value_name = "average|mean",
+        default_value = "average",
+        value_parser = |value: &str| ScoreBasis::from_flag(value)
+            .ok_or("must be one of mean/average")
+    )]
+    pub mean_basis: ScoreBasis,
+    /// Ids per average score query.
+    #[arg(long, value_name = "N")]
+    pub chunk_size: Option<usize>,
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn missing_command_defaults_to_fetch() {
+        let cli = Cli::parse_from(with_default_command(args(
+            "avg_anilist_index koisland anime --format json --metric delta",
+        )));
+        let Command::Fetch(fetch) = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(fetch.username, "koisland");
+        assert_eq!(fetch.format, OutputFormat::Json);
+        assert_eq!(fetch.metric, Metric::Delta);
+        assert_eq!(fetch.precision, DEFAULT_PRECISION);
+    }
+
+    #[test]
+    fn commands_are_not_shadowed() {
+        let cli = Cli::parse_from(with_default_command(args(
+            "avg_anilist_index diff old.json new.json",
+        )));
+        assert!(matches!(cli.command, Command::Diff(_)));
+        assert_eq!(
+            with_default_command(args("avg_anilist_index --help")),
+            args("avg_anilist_index --help")
+        );
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        let parsed = Cli::try_parse_from(with_default_command(args(
+            "avg_anilist_index koisland ANIME --format xlsx",
+        )));
+        assert!(parsed.is_err());
+        let cli = Cli::parse_from(args("avg_anilist_index fetch koisland ANIME --format MD"));
+        let Command::Fetch(fetch) = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(fetch.format, OutputFormat::Markdown);
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        let parsed = Cli::try_parse_from(args(
+            "avg_anilist_index fetch koisland ANIME --status COMPLETED,WATCHED",
+        ));
+        let err = parsed.unwrap_err().to_string();
+        assert!(err.contains("Unknown status 'WATCHED'"), "{err}");
+    }
+
+    #[test]
+    fn last_clobber_policy_wins() {
+        let fetch = |line: &str| {
+            let Command::Fetch(fetch) = Cli::parse_from(args(line)).command else {
+                panic!("expected fetch");
+            };
+            fetch
+        };
+
+        let fetch_args = fetch("avg_anilist_index fetch koisland ANIME --no-clobber --overwrite");
+        assert!(!fetch_args.no_clobber);
+        let fetch_args = fetch("avg_anilist_index fetch koisland ANIME --overwrite --no-clobber");
+        assert!(fetch_args.no_clobber);
+        assert!(!fetch_args.overwrite);
+    }
+
+    #[test]
+    fn numeric_flags_are_range_checked() {
+        for flags in [
+            "--tag-min-rank 101",
+            "--tag-min-rank=-1",
+            "--precision 16",
+            "--warn-threshold 1.5",
+        ] {
+            let line = format!("avg_anilist_index fetch koisland ANIME {flags}");
+            assert!(Cli::try_parse_from(args(&line)).is_err(), "{flags}");
+        }
+        let cli = Cli::parse_from(args(
+            "avg_anilist_index fetch koisland ANIME --tag-min-rank 100 --precision 0 --warn-threshold 1",
+        ));
+        let Command::Fetch(fetch) = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(
+            (fetch.tag_min_rank, fetch.precision, fetch.warn_threshold),
+            (100, 0, 1.0)
+        );
+        assert!(
+            Cli::try_parse_from(args("avg_anilist_index compare a b ANIME --precision 99"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn quiet_conflicts_with_verbose() {
+        let parsed = Cli::try_parse_from(with_default_command(args(
+            "avg_anilist_index koisland ANIME --quiet -v",
+        )));
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn seed_is_read_from_flag_or_env() {
+        // Checks the wiring rather than setting ANILIST_SEED, which would race with the
+        // other tests parsing arguments in parallel.
+        let command = Cli::command();
+        let seed_arg = command
+            .find_subcommand("fetch")
+            .unwrap()
+            .get_arguments()
+            .find(|arg| arg.get_id() == "seed")
+            .unwrap();
+        assert_eq!(
+            seed_arg.get_env(),
+            Some(std::ffi::OsStr::new("ANILIST_SEED"))
+        );
+
+        let cli = Cli::parse_from(with_default_command(args(
+            "avg_anilist_index koisland ANIME --sample 10 --seed 3",
+        )));
+        let Command::Fetch(fetch) = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(fetch.seed, Some(3));
+    }
+
+    #[test]
+    fn token_is_read_from_file() {
+        let path = std::env::temp_dir().join(format!("anilist_token_{}", std::process::id()));
+        fs::write(&path, "  secret-token\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let cli = Cli::parse_from(args(&format!(
+            "avg_anilist_index check --token-file {path}"
+        )));
+        let Command::Check(check) = cli.command else {
+            panic!("expected check");
+        };
+        assert_eq!(check.token(None), Ok(Some("secret-token".to_string())));
+        // The file wins over ANILIST_TOKEN rather than conflicting with it.
+        assert_eq!(
+            check.token(Some("abc".to_string())),
+            Ok(Some("secret-token".to_string()))
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(check.token(None).unwrap_err().starts_with("Unable to read"));
+
+        let parsed = Cli::try_parse_from(args(&format!(
+            "avg_anilist_index check --token abc --token-file {path}"
+        )));
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn token_falls_back_to_env() {
+        let cli = Cli::parse_from(args("avg_anilist_index check"));
+        let Command::Check(check) = cli.command else {
+            panic!("expected check");
+        };
+        assert_eq!(
+            check.token(Some("abc".to_string())),
+            Ok(Some("abc".to_string()))
+        );
+
+        let cli = Cli::parse_from(args("avg_anilist_index check --token def"));
+        let Command::Check(check) = cli.command else {
+            panic!("expected check");
+        };
+        assert_eq!(
+            check.token(Some("abc".to_string())),
+            Ok(Some("def".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_column_is_rejected() {
+        let parsed = Cli::try_parse_from(with_default_command(args(
+            "avg_anilist_index koisland ANIME --columns-order title,score",
+        )));
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn invalid_choice_is_rejected() {
+        let parsed = Cli::try_parse_from(with_default_command(args(
+            "avg_anilist_index koisland ANIME --metric median",
+        )));
+        assert!(parsed.is_err());
+    }
+}
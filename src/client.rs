@@ -0,0 +1,762 @@
+use futures_util::future::BoxFuture;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::AnilistError;
+use crate::queries::QUERY_VIEWER;
+
+const ANILIST_URL: &str = "https://graphql.anilist.co/";
+/// User-Agent sent unless `--user-agent` overrides it.
+const DEFAULT_USER_AGENT: &str = concat!("AnilistScores/", env!("CARGO_PKG_VERSION"));
+/// Times a rate-limited query is retried before giving up.
+const MAX_RETRIES: u32 = 3;
+/// Upper bound of the random delay added to each retry so concurrent runs don't retry in sync.
+pub const DEFAULT_RETRY_JITTER: Duration = Duration::from_millis(500);
+/// Wait used when a rate-limited response has no `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Times a rate-limited query is retried before giving up.
+    pub max_retries: u32,
+    /// Upper bound of the random delay added to each retry wait.
+    pub jitter: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: MAX_RETRIES,
+            jitter: DEFAULT_RETRY_JITTER,
+        }
+    }
+}
+
+/// Point after which queries fail with `RuntimeExceeded` instead of being sent, bounding
+/// the whole run rather than each request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    at: Instant,
+    limit: Duration,
+}
+
+impl Deadline {
+    /// Deadline `limit` from now.
+    pub fn after(limit: Duration) -> Deadline {
+        Deadline {
+            at: Instant::now() + limit,
+            limit,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        Instant::now() >= self.at
+    }
+
+    pub fn instant(&self) -> tokio::time::Instant {
+        tokio::time::Instant::from_std(self.at)
+    }
+
+    pub fn error(&self) -> AnilistError {
+        AnilistError::RuntimeExceeded {
+            limit_secs: self.limit.as_secs(),
+        }
+    }
+}
+
+/// Add a random delay of up to `jitter` to `wait`.
+fn jittered(wait: Duration, jitter: Duration, rng: &mut impl Rng) -> Duration {
+    if jitter.is_zero() {
+        wait
+    } else {
+        wait + rng.gen_range(Duration::ZERO..=jitter)
+    }
+}
+
+/// Seconds to wait before retrying a rate-limited query, from its `Retry-After` header.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    resp.headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Answers the queries of an `AnilistClient` in place of its HTTP endpoints, e.g. with
+/// canned responses so the code assembling queries and aligning their results can be
+/// tested without a server.
+pub trait Transport: fmt::Debug + Send + Sync {
+    fn query<'a>(&'a self, json_query: &'a Value) -> BoxFuture<'a, Result<Value, AnilistError>>;
+}
+
+/// Outcome of `check`: whether the token was accepted, and the rate limit left after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    /// Name of the token's user, or why it was rejected.
+    pub viewer: Result<String, AnilistError>,
+    /// `X-RateLimit-Remaining` of the response, if sent.
+    pub rate_limit_remaining: Option<u32>,
+}
+
+/// Every request to Anilist goes through here, so the User-Agent, auth, rate limit retries
+/// and request counting apply to all of them.
+#[derive(Debug, Clone)]
+pub struct AnilistClient {
+    http: Client,
+    /// Endpoints tried in order, failing over on connection errors.
+    urls: Vec<String>,
+    /// Index in `urls` of the endpoint that last answered, shared by clones.
+    active: Arc<AtomicUsize>,
+    retry: RetryPolicy,
+    token: Option<String>,
+    /// Kept to rebuild `http` with other connection settings.
+    user_agent: String,
+    deadline: Option<Deadline>,
+    /// HTTP requests sent so far, including retries, shared by clones.
+    requests: Arc<AtomicUsize>,
+    /// Sends queries instead of `http` if set. Retries and request counting are left to it.
+    transport: Option<Arc<dyn Transport>>,
+}
+
+fn http_client(user_agent: &str, pool_size: Option<usize>) -> Client {
+    let mut builder = Client::builder().user_agent(user_agent);
+    if let Some(pool_size) = pool_size {
+        builder = builder.pool_max_idle_per_host(pool_size);
+    }
+    builder.build().expect("Unable to build HTTP client.")
+}
+
+impl AnilistClient {
+    /// Client for Anilist sending `user_agent`, or `DEFAULT_USER_AGENT` if unset.
+    pub fn new(user_agent: Option<&str>, retry: RetryPolicy) -> AnilistClient {
+        let user_agent = user_agent.unwrap_or(DEFAULT_USER_AGENT);
+        AnilistClient {
+            http: http_client(user_agent, None),
+            urls: vec![ANILIST_URL.to_string()],
+            active: Arc::new(AtomicUsize::new(0)),
+            retry,
+            token: None,
+            user_agent: user_agent.to_string(),
+            deadline: None,
+            requests: Arc::new(AtomicUsize::new(0)),
+            transport: None,
+        }
+    }
+
+    /// Send queries through `transport` rather than over HTTP, if set. `check` still
+    /// goes over HTTP.
+    pub fn with_transport(mut self, transport: Option<Arc<dyn Transport>>) -> AnilistClient {
+        self.transport = transport;
+        self
+    }
+
+    /// Abort queries still running at `deadline`, and fail later ones without sending
+    /// them, if set.
+    pub fn with_deadline(mut self, deadline: Option<Deadline>) -> AnilistClient {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Keep at most `pool_size` idle connections per host, instead of reqwest's unbounded
+    /// default, if set.
+    pub fn with_pool_size(mut self, pool_size: Option<usize>) -> AnilistClient {
+        self.http = http_client(&self.user_agent, pool_size);
+        self
+    }
+
+    /// Send queries to `url` instead of Anilist.
+    pub fn with_url(self, url: &str) -> AnilistClient {
+        self.with_endpoints(&[url.to_string()])
+    }
+
+    /// Send queries to the first of `urls` that accepts a connection. Keeps Anilist if
+    /// `urls` is empty.
+    pub fn with_endpoints(mut self, urls: &[String]) -> AnilistClient {
+        if !urls.is_empty() {
+            self.urls = urls.to_vec();
+            self.active = Arc::new(AtomicUsize::new(0));
+        }
+        self
+    }
+
+    /// Count requests in `requests`, so clients built for the same run add up to one total.
+    pub fn with_request_count(mut self, requests: Arc<AtomicUsize>) -> AnilistClient {
+        self.requests = requests;
+        self
+    }
+
+    /// HTTP requests this client, its clones and clients sharing its count have sent,
+    /// including retries.
+    pub fn requests_made(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Authenticate queries with `token`.
+    pub fn with_token(mut self, token: Option<String>) -> AnilistClient {
+        self.token = token;
+        self
+    }
+
+    fn post(&self, url: &str, json_query: &Value) -> RequestBuilder {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let request = self
+            .http
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(json_query.to_string());
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// POST a query to the endpoint that last answered, moving on to the next one when it
+    /// can't be reached. Endpoints that failed are not retried.
+    async fn send(&self, json_query: &Value) -> Result<Response, AnilistError> {
+        let first = self.active.load(Ordering::SeqCst);
+        let mut last_err = None;
+        for (i, url) in self.urls.iter().enumerate().skip(first) {
+            match self.post(url, json_query).send().await {
+                Ok(resp) => {
+                    if i != first {
+                        eprintln!("Endpoint unreachable, failed over to {url}.");
+                        self.active.store(i, Ordering::SeqCst);
+                    }
+                    return Ok(resp);
+                }
+                Err(err) if err.is_connect() || err.is_timeout() => {
+                    eprintln!("Unable to reach {url}: {err}");
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(AnilistError::Request(err.to_string())),
+            }
+        }
+        Err(AnilistError::Request(match last_err {
+            Some(err) => err.to_string(),
+            None => "No endpoint left to query.".to_string(),
+        }))
+    }
+
+    /// POST a query, waiting out up to `retry.max_retries` rate-limited (429) responses.
+    /// Fails with `RuntimeExceeded` once past the client's deadline, if any.
+    pub async fn query(&self, json_query: &Value) -> Result<Value, AnilistError> {
+        let Some(deadline) = self.deadline else {
+            return self.query_with_retries(json_query).await;
+        };
+        if deadline.passed() {
+            return Err(deadline.error());
+        }
+        tokio::time::timeout_at(deadline.instant(), self.query_with_retries(json_query))
+            .await
+            .unwrap_or_else(|_| Err(deadline.error()))
+    }
+
+    async fn query_with_retries(&self, json_query: &Value) -> Result<Value, AnilistError> {
+        if let Some(transport) = &self.transport {
+            return transport.query(json_query).await;
+        }
+        let max_retries = self.retry.max_retries;
+        let mut rng = StdRng::from_entropy();
+        let mut retries = 0;
+        loop {
+            let resp = self.send(json_query).await?;
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+                if retries >= max_retries {
+                    return Err(AnilistError::RateLimited);
+                }
+                retries += 1;
+                let wait = jittered(
+                    retry_after(&resp).unwrap_or(DEFAULT_RETRY_AFTER),
+                    self.retry.jitter,
+                    &mut rng,
+                );
+                eprintln!(
+                    "Rate limited. Retrying in {:.1}s ({retries}/{max_retries})...",
+                    wait.as_secs_f64()
+                );
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            if resp.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                return Err(AnilistError::QueryTooComplex(
+                    "Request body too large.".to_string(),
+                ));
+            }
+            let resp = resp.text().await;
+            // Get json
+            return if let Ok(resp) = resp {
+                if let Ok(result) = serde_json::from_str(&resp) {
+                    Ok(result)
+                } else {
+                    Err(AnilistError::MalformedResponse(
+                        "Cannot convert to json.".to_string(),
+                    ))
+                }
+            } else {
+                Err(AnilistError::Request(
+                    "Cannot retrieve query response text.".to_string(),
+                ))
+            };
+        }
+    }
+
+    /// Issue a `Viewer` query to confirm Anilist is reachable and the token is accepted.
+    /// Err only if no response was received at all.
+    pub async fn check(&self) -> Result<HealthCheck, AnilistError> {
+        let resp = self.send(&json!({ "query": QUERY_VIEWER })).await?;
+
+        let rate_limit_remaining = resp
+            .headers()
+            .get("X-RateLimit-Remaining")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok());
+        let body = resp
+            .text()
+            .await
+            .map_err(|err| AnilistError::Request(err.to_string()))?;
+        let body: Value = serde_json::from_str(&body)
+            .map_err(|err| AnilistError::MalformedResponse(err.to_string()))?;
+        let viewer = match body
+            .pointer("/data/Viewer/name")
+            .and_then(|name| name.as_str())
+        {
+            Some(name) => Ok(name.to_string()),
+            None => Err(AnilistError::from_response(&body)),
+        };
+        Ok(HealthCheck {
+            viewer,
+            rate_limit_remaining,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const NO_JITTER: RetryPolicy = RetryPolicy {
+        max_retries: 1,
+        jitter: Duration::ZERO,
+    };
+    const RATE_LIMITED: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 20\r\nConnection: close\r\n\r\n{\"data\":{\"ok\":true}}";
+
+    fn read_request(stream: &mut impl Read) -> String {
+        let mut request = vec![];
+        let mut buf = [0; 1024];
+        loop {
+            let read = stream.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|len| len.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if request.len() >= header_end + 4 + content_length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&request).into_owned()
+    }
+
+    /// Raw HTTP response with `status`, e.g. "429 Too Many Requests", `headers` and a
+    /// JSON `body`.
+    pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect();
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// 200 response with a JSON `body`.
+    pub(crate) fn ok_response(body: &str) -> String {
+        response("200 OK", &[], body)
+    }
+
+    /// What the mock server does with one request.
+    pub(crate) enum Reply {
+        /// Answer with this raw HTTP response.
+        Respond(String),
+        /// Read the request and never answer.
+        Hang,
+        /// Read the request and close the connection without answering.
+        Reset,
+    }
+
+    impl From<&str> for Reply {
+        fn from(response: &str) -> Reply {
+            Reply::Respond(response.to_string())
+        }
+    }
+
+    impl From<String> for Reply {
+        fn from(response: String) -> Reply {
+            Reply::Respond(response)
+        }
+    }
+
+    /// Serve each reply in order to one connection, recording the requests received.
+    pub(crate) fn mock_server<R: Into<Reply>>(
+        replies: Vec<R>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let replies: Vec<Reply> = replies.into_iter().map(Into::into).collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut stream);
+                received.lock().unwrap().push(request);
+                match reply {
+                    Reply::Respond(response) => stream.write_all(response.as_bytes()).unwrap(),
+                    Reply::Hang => thread::sleep(Duration::from_secs(2)),
+                    Reply::Reset => drop(stream),
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    /// Answers each query with the response of the first key its text contains, recording
+    /// the queries received. No server involved.
+    #[derive(Debug, Default)]
+    pub(crate) struct Canned {
+        responses: Vec<(String, Value)>,
+        pub(crate) queries: Mutex<Vec<Value>>,
+    }
+
+    impl Canned {
+        pub(crate) fn new(responses: Vec<(&str, Value)>) -> Arc<Canned> {
+            Arc::new(Canned {
+                responses: responses
+                    .into_iter()
+                    .map(|(key, response)| (key.to_string(), response))
+                    .collect(),
+                ..Default::default()
+            })
+        }
+    }
+
+    impl Transport for Canned {
+        fn query<'a>(
+            &'a self,
+            json_query: &'a Value,
+        ) -> BoxFuture<'a, Result<Value, AnilistError>> {
+            self.queries.lock().unwrap().push(json_query.clone());
+            let text = json_query["query"].as_str().unwrap_or_default();
+            let response = self
+                .responses
+                .iter()
+                .find(|(key, _)| text.contains(key.as_str()))
+                .map(|(_, response)| response.clone())
+                .ok_or_else(|| AnilistError::Request(format!("No canned response for {text}")));
+            Box::pin(async move { response })
+        }
+    }
+
+    fn mock_client(url: &str) -> AnilistClient {
+        AnilistClient::new(None, NO_JITTER).with_url(url)
+    }
+
+    #[tokio::test]
+    async fn retries_after_rate_limit() {
+        let (url, requests) = mock_server(vec![RATE_LIMITED, OK]);
+        let res = mock_client(&url).query(&json!({})).await;
+
+        assert_eq!(res, Ok(json!({"data": {"ok": true}})));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn requests_are_counted_per_client() {
+        let (url, _) = mock_server(vec![RATE_LIMITED, OK, OK]);
+        let client = mock_client(&url);
+        client.query(&json!({})).await.unwrap();
+        // Clones add to the same count, other clients keep their own.
+        client.clone().query(&json!({})).await.unwrap();
+
+        assert_eq!(client.requests_made(), 3);
+        assert_eq!(mock_client(&url).requests_made(), 0);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (url, requests) = mock_server(vec![RATE_LIMITED, RATE_LIMITED]);
+        let res = mock_client(&url).query(&json!({})).await;
+
+        assert_eq!(res, Err(AnilistError::RateLimited));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    // The clock is paused, so the wait is skipped rather than slept through.
+    #[tokio::test(start_paused = true)]
+    async fn honors_retry_after() {
+        let (url, _) = mock_server(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            OK,
+        ]);
+        let start = tokio::time::Instant::now();
+        let res = mock_client(&url).query(&json!({})).await;
+
+        assert!(res.is_ok());
+        assert!(start.elapsed() >= Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn slow_query_is_aborted_at_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            thread::sleep(Duration::from_secs(5));
+            let _ = stream.write_all(OK.as_bytes());
+        });
+        let client =
+            mock_client(&url).with_deadline(Some(Deadline::after(Duration::from_millis(200))));
+
+        let start = Instant::now();
+        let res = client.query(&json!({})).await;
+        assert_eq!(res, Err(AnilistError::RuntimeExceeded { limit_secs: 0 }));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // Past the deadline, nothing is sent at all.
+        assert!(matches!(
+            client.query(&json!({})).await,
+            Err(AnilistError::RuntimeExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_user_agent_and_token() {
+        let (url, requests) = mock_server(vec![OK, OK]);
+        mock_client(&url).query(&json!({})).await.unwrap();
+        AnilistClient::new(Some("scores-bot/2"), NO_JITTER)
+            .with_pool_size(Some(4))
+            .with_url(&url)
+            .with_token(Some("secret".to_string()))
+            .query(&json!({}))
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let header = |request: &str, name: &str| {
+            request.lines().find_map(|line| {
+                line.to_lowercase()
+                    .strip_prefix(&format!("{name}:"))
+                    .map(|value| value.trim().to_string())
+            })
+        };
+        assert_eq!(
+            header(&requests[0], "user-agent"),
+            Some(format!("anilistscores/{}", env!("CARGO_PKG_VERSION")))
+        );
+        assert_eq!(header(&requests[0], "authorization"), None);
+        assert_eq!(
+            header(&requests[1], "user-agent"),
+            Some("scores-bot/2".to_string())
+        );
+        assert_eq!(
+            header(&requests[1], "authorization"),
+            Some("bearer secret".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn check_reports_viewer_and_rate_limit() {
+        let (url, _) = mock_server(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-RateLimit-Remaining: 89\r\nContent-Length: 40\r\nConnection: close\r\n\r\n{\"data\":{\"Viewer\":{\"id\":1,\"name\":\"ko\"}}}",
+        ]);
+        let check = mock_client(&url).check().await.unwrap();
+
+        assert_eq!(check.viewer, Ok("ko".to_string()));
+        assert_eq!(check.rate_limit_remaining, Some(89));
+    }
+
+    #[tokio::test]
+    async fn check_reports_rejected_token() {
+        let (url, _) = mock_server(vec![
+            "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: 65\r\nConnection: close\r\n\r\n{\"errors\":[{\"message\":\"Invalid token\",\"status\":400}],\"data\":null}",
+        ]);
+        let check = mock_client(&url)
+            .with_token(Some("bad".to_string()))
+            .check()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            check.viewer,
+            Err(AnilistError::Unauthorized("Invalid token".to_string()))
+        );
+        assert_eq!(check.rate_limit_remaining, None);
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_next_endpoint() {
+        // Nothing listens on a port once its listener is dropped.
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/", listener.local_addr().unwrap())
+        };
+        let (url, requests) = mock_server(vec![OK, OK]);
+        let client = AnilistClient::new(None, NO_JITTER).with_endpoints(&[closed, url]);
+
+        assert!(client.query(&json!({})).await.is_ok());
+        // The failed endpoint is skipped from then on, also by clones.
+        assert!(client.clone().query(&json!({})).await.is_ok());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(client.active.load(Ordering::SeqCst), 1);
+    }
+
+    /// How each kind of failure is handled: the error kind callers see once the response
+    /// is checked for `data`, or None on success, and how many requests it took. Only
+    /// rate limits are retried. Anilist reports other failures in the GraphQL `errors`
+    /// array, which is classified by its status whatever the HTTP status.
+    #[tokio::test]
+    async fn retry_classification_matrix() {
+        let reply = |status: &str, body: &str| {
+            Reply::Respond(response(status, &[("Retry-After", "0")], body))
+        };
+        let ok = reply("200 OK", r#"{"data":{"ok":true}}"#);
+        let cases = [
+            (
+                "400 invalid query",
+                vec![reply(
+                    "400 Bad Request",
+                    r#"{"errors":[{"message":"Validation error","status":400}],"data":null}"#,
+                )],
+                Some("malformed_response"),
+                1,
+            ),
+            (
+                "400 invalid token",
+                vec![reply(
+                    "400 Bad Request",
+                    r#"{"errors":[{"message":"Invalid token","status":400}],"data":null}"#,
+                )],
+                Some("unauthorized"),
+                1,
+            ),
+            (
+                "401 rejected token",
+                vec![reply(
+                    "401 Unauthorized",
+                    r#"{"errors":[{"message":"Invalid token","status":401}],"data":null}"#,
+                )],
+                Some("unauthorized"),
+                1,
+            ),
+            (
+                "404 unknown user",
+                vec![reply(
+                    "404 Not Found",
+                    r#"{"errors":[{"message":"Not Found.","status":404}],"data":null}"#,
+                )],
+                Some("user_not_found"),
+                1,
+            ),
+            (
+                "413 too complex",
+                vec![reply("413 Payload Too Large", "")],
+                Some("query_too_complex"),
+                1,
+            ),
+            (
+                "429 then ok",
+                vec![reply("429 Too Many Requests", ""), ok],
+                None,
+                2,
+            ),
+            (
+                "429 past max retries",
+                vec![
+                    reply("429 Too Many Requests", ""),
+                    reply("429 Too Many Requests", ""),
+                ],
+                Some("rate_limited"),
+                2,
+            ),
+            (
+                "500 with html body",
+                vec![reply(
+                    "500 Internal Server Error",
+                    "<h1>Internal Server Error</h1>",
+                )],
+                Some("malformed_response"),
+                1,
+            ),
+            (
+                "500 with graphql errors",
+                vec![reply(
+                    "500 Internal Server Error",
+                    r#"{"errors":[{"message":"Internal Server Error","status":500}],"data":null}"#,
+                )],
+                Some("malformed_response"),
+                1,
+            ),
+            (
+                "hang past the run deadline",
+                vec![Reply::Hang],
+                Some("runtime_exceeded"),
+                1,
+            ),
+            ("connection reset", vec![Reply::Reset], Some("request"), 1),
+        ];
+
+        for (name, replies, expected, request_count) in cases {
+            let (url, requests) = mock_server(replies);
+            // Requests have no timeout of their own, only the run's deadline.
+            let client =
+                mock_client(&url).with_deadline(Some(Deadline::after(Duration::from_millis(300))));
+            let res = client
+                .query(&json!({}))
+                .await
+                .and_then(|res| AnilistError::require_path(&res, &["data", "ok"]).cloned());
+
+            assert_eq!(res.err().map(|err| err.kind()), expected, "{name}");
+            assert_eq!(requests.lock().unwrap().len(), request_count, "{name}");
+        }
+    }
+
+    #[test]
+    fn jitter_is_bounded_and_seeded() {
+        let wait = Duration::from_secs(2);
+        let jitter = Duration::from_millis(250);
+        let first = jittered(wait, jitter, &mut StdRng::seed_from_u64(7));
+        let second = jittered(wait, jitter, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(first, second);
+        assert!(first >= wait && first <= wait + jitter);
+        assert_eq!(
+            jittered(wait, Duration::ZERO, &mut StdRng::seed_from_u64(7)),
+            wait
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::{pearson, AnilistScores};
+
+/// How two users scored the titles both of them have scored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserComparison {
+    pub shared: usize,
+    /// Mean score of the first user on the shared titles, out of 100.
+    pub mean_score: f64,
+    /// Mean score of the other user on the shared titles, out of 100.
+    pub other_mean_score: f64,
+    pub mean_abs_difference: f64,
+    pub correlation: Option<f64>,
+}
+
+/// Scored titles of every list, normalized to the 100-point scale.
+fn normalized_scores(lists: &[AnilistScores]) -> HashMap<i64, f64> {
+    lists
+        .iter()
+        .flat_map(|list| {
+            let scale = list.user_score_scale();
+            list.anilist_id
+                .iter()
+                .zip(list.user_score.iter())
+                .filter(|(_, score)| **score != 0)
+                .map(move |(id, score)| (*id, (score * scale) as f64))
+        })
+        .collect()
+}
+
+pub fn compare_users(lists: &[AnilistScores], other_lists: &[AnilistScores]) -> UserComparison {
+    let scores = normalized_scores(lists);
+    let other_scores = normalized_scores(other_lists);
+    let pairs: Vec<(f64, f64)> = scores
+        .iter()
+        .filter_map(|(id, score)| other_scores.get(id).map(|other| (*score, *other)))
+        .collect();
+
+    let shared = pairs.len();
+    let mean_of = |values: Vec<f64>| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    };
+    UserComparison {
+        shared,
+        mean_score: mean_of(pairs.iter().map(|(score, _)| *score).collect()),
+        other_mean_score: mean_of(pairs.iter().map(|(_, other)| *other).collect()),
+        mean_abs_difference: mean_of(
+            pairs
+                .iter()
+                .map(|(score, other)| (score - other).abs())
+                .collect(),
+        ),
+        correlation: pearson(&pairs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(ids: Vec<i64>, user_score: Vec<i64>) -> AnilistScores {
+        let global_avg_score = vec![0; user_score.len()];
+        AnilistScores {
+            anilist_id: ids,
+            ..AnilistScores::fixture(user_score, global_avg_score)
+        }
+    }
+
+    #[test]
+    fn only_titles_scored_by_both_are_compared() {
+        // The other user scores out of 10.
+        let comparison = compare_users(
+            &[list(vec![1, 2, 3, 4], vec![80, 60, 90, 70])],
+            &[list(vec![1, 2, 3, 5], vec![9, 5, 0, 8])],
+        );
+
+        assert_eq!(comparison.shared, 2);
+        assert_eq!(comparison.mean_score, 70.0);
+        assert_eq!(comparison.other_mean_score, 70.0);
+        assert_eq!(comparison.mean_abs_difference, 10.0);
+        assert!((comparison.correlation.unwrap() - 1.0).abs() < 1e-9);
+    }
+}
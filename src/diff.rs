@@ -0,0 +1,149 @@
+use polars::prelude::{df, CsvWriter, DataFrame, NamedFrom, SerWriter, Series};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoreChange {
+    pub anilist_id: i64,
+    pub kind: ChangeKind,
+    pub old_score: Option<i64>,
+    pub new_score: Option<i64>,
+}
+
+/// User score of each title in a run saved with `--format json`.
+pub fn load_run(fname: &str) -> Result<BTreeMap<i64, i64>, String> {
+    let contents =
+        fs::read_to_string(fname).map_err(|err| format!("Unable to read {fname}: {err}"))?;
+    let rows: Value = serde_json::from_str(&contents)
+        .map_err(|err| format!("Unable to parse {fname} as json: {err}"))?;
+
+    let mut scores = BTreeMap::new();
+    for row in rows.as_array().into_iter().flatten() {
+        if let (Some(id), Some(score)) = (
+            row.get("anilist_id").and_then(|value| value.as_i64()),
+            row.get("user_score").and_then(|value| value.as_i64()),
+        ) {
+            scores.insert(id, score);
+        }
+    }
+    Ok(scores)
+}
+
+/// Titles added, removed, or rescored between two runs, ordered by id.
+pub fn diff_runs(old: &BTreeMap<i64, i64>, new: &BTreeMap<i64, i64>) -> Vec<ScoreChange> {
+    let mut changes: Vec<ScoreChange> = vec![];
+
+    for (id, new_score) in new.iter() {
+        match old.get(id) {
+            None => changes.push(ScoreChange {
+                anilist_id: *id,
+                kind: ChangeKind::Added,
+                old_score: None,
+                new_score: Some(*new_score),
+            }),
+            Some(old_score) if old_score != new_score => changes.push(ScoreChange {
+                anilist_id: *id,
+                kind: ChangeKind::Changed,
+                old_score: Some(*old_score),
+                new_score: Some(*new_score),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (id, old_score) in old.iter() {
+        if !new.contains_key(id) {
+            changes.push(ScoreChange {
+                anilist_id: *id,
+                kind: ChangeKind::Removed,
+                old_score: Some(*old_score),
+                new_score: None,
+            });
+        }
+    }
+
+    changes.sort_by_key(|change| change.anilist_id);
+    changes
+}
+
+pub fn changes_dataframe(changes: &[ScoreChange]) -> Result<DataFrame, String> {
+    let df = df!(
+        "anilist_id" => changes.iter().map(|change| change.anilist_id).collect::<Vec<i64>>(),
+        "change" => changes.iter().map(|change| change.kind.as_str()).collect::<Vec<&str>>(),
+        "old_score" => changes.iter().map(|change| change.old_score).collect::<Vec<Option<i64>>>(),
+        "new_score" => changes.iter().map(|change| change.new_score).collect::<Vec<Option<i64>>>()
+    );
+    df.map_err(|err| format!("Unable to save score changes to dataframe: {:?}", err))
+}
+
+/// Diff two saved runs, write the changelog CSV to `output` and print a summary.
+pub fn diff_files(old: &str, new: &str, output: &str) -> Result<(), String> {
+    let changes = diff_runs(&load_run(old)?, &load_run(new)?);
+    let mut changes_df = changes_dataframe(&changes)?;
+
+    let output_fh =
+        File::create(output).map_err(|_| format!("Unable to create file at {output}."))?;
+    CsvWriter::new(output_fh)
+        .has_header(true)
+        .finish(&mut changes_df)
+        .map_err(|_| format!("Unable to save file to {output}"))?;
+
+    for kind in [ChangeKind::Added, ChangeKind::Removed, ChangeKind::Changed] {
+        let count = changes.iter().filter(|change| change.kind == kind).count();
+        println!("{}: {count}", kind.as_str());
+    }
+    println!("Wrote score changes to {output}.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_added_removed_and_changed() {
+        let old = BTreeMap::from([(1, 70), (2, 80), (3, 90)]);
+        let new = BTreeMap::from([(2, 85), (3, 90), (4, 60)]);
+
+        assert_eq!(
+            diff_runs(&old, &new),
+            vec![
+                ScoreChange {
+                    anilist_id: 1,
+                    kind: ChangeKind::Removed,
+                    old_score: Some(70),
+                    new_score: None,
+                },
+                ScoreChange {
+                    anilist_id: 2,
+                    kind: ChangeKind::Changed,
+                    old_score: Some(80),
+                    new_score: Some(85),
+                },
+                ScoreChange {
+                    anilist_id: 4,
+                    kind: ChangeKind::Added,
+                    old_score: None,
+                    new_score: Some(60),
+                },
+            ]
+        );
+    }
+}
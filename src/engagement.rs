@@ -0,0 +1,124 @@
+use polars::prelude::{df, DataFrame, NamedFrom, Series};
+
+use crate::AnilistScores;
+
+/// Lists fetched only for `--include-status-changes`, besides the default lists.
+pub const STATUS_CHANGE_LISTS: &[&str] = &["Dropped", "Rewatching"];
+
+/// How the user engaged with their titles, from each entry's status and repeat count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engagement {
+    CompletedOnce,
+    /// Completed more than once, or being rewatched.
+    Rewatched,
+    Dropped,
+    Watching,
+}
+
+impl Engagement {
+    pub const ALL: [Engagement; 4] = [
+        Engagement::CompletedOnce,
+        Engagement::Rewatched,
+        Engagement::Dropped,
+        Engagement::Watching,
+    ];
+
+    /// Engagement of an entry with `status` repeated `repeat` times. None for paused and
+    /// planned entries.
+    pub fn of(status: &str, repeat: i64) -> Option<Engagement> {
+        match status {
+            "COMPLETED" if repeat > 0 => Some(Engagement::Rewatched),
+            "COMPLETED" => Some(Engagement::CompletedOnce),
+            "REPEATING" => Some(Engagement::Rewatched),
+            "DROPPED" => Some(Engagement::Dropped),
+            "CURRENT" => Some(Engagement::Watching),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Engagement::CompletedOnce => "completed_once",
+            Engagement::Rewatched => "rewatched",
+            Engagement::Dropped => "dropped",
+            Engagement::Watching => "watching",
+        }
+    }
+}
+
+/// Titles of one engagement and how far the user scores them from the global average.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngagementCount {
+    pub engagement: Engagement,
+    pub titles: usize,
+    /// Mean of the deltas of its titles with both scores. None if none have.
+    pub mean_delta: Option<f64>,
+}
+
+/// Titles of each engagement across `lists`, in `Engagement::ALL` order.
+pub fn engagement_counts(lists: &[AnilistScores]) -> Vec<EngagementCount> {
+    Engagement::ALL
+        .iter()
+        .map(|engagement| {
+            let mut titles = 0;
+            let mut deltas = vec![];
+            for list in lists {
+                for (i, delta) in list.deltas().into_iter().enumerate() {
+                    let repeat = list.repeat.get(i).copied().unwrap_or(0);
+                    if Engagement::of(&list.status[i], repeat) != Some(*engagement) {
+                        continue;
+                    }
+                    titles += 1;
+                    deltas.extend(delta);
+                }
+            }
+            EngagementCount {
+                engagement: *engagement,
+                titles,
+                mean_delta: (!deltas.is_empty())
+                    .then(|| deltas.iter().sum::<i64>() as f64 / deltas.len() as f64),
+            }
+        })
+        .collect()
+}
+
+/// One row per engagement.
+pub fn engagement_dataframe(counts: &[EngagementCount]) -> Result<DataFrame, String> {
+    let df = df!(
+        "engagement" => counts.iter().map(|count| count.engagement.label()).collect::<Vec<&str>>(),
+        "titles" => counts.iter().map(|count| count.titles as i64).collect::<Vec<i64>>(),
+        "mean_delta" => counts.iter().map(|count| count.mean_delta).collect::<Vec<Option<f64>>>()
+    );
+    df.map_err(|err| format!("Unable to save status changes to dataframe: {:?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_count_as_rewatched() {
+        let list = AnilistScores {
+            status: ["COMPLETED", "COMPLETED", "DROPPED", "CURRENT", "PAUSED"]
+                .map(String::from)
+                .to_vec(),
+            repeat: vec![2, 0, 0, 0, 0],
+            ..AnilistScores::fixture(vec![90, 80, 30, 70, 60], vec![70, 70, 60, 0, 60])
+        };
+
+        let counts = engagement_counts(&[list]);
+        let summary: Vec<(&str, usize, Option<f64>)> = counts
+            .iter()
+            .map(|count| (count.engagement.label(), count.titles, count.mean_delta))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("completed_once", 1, Some(10.0)),
+                ("rewatched", 1, Some(20.0)),
+                ("dropped", 1, Some(-30.0)),
+                ("watching", 1, None),
+            ]
+        );
+    }
+}
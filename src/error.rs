@@ -0,0 +1,228 @@
+use serde_json::{json, Value};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnilistError {
+    /// The user does not exist or their lists are not visible.
+    UserNotFound(String),
+    RateLimited,
+    /// Anilist rejected the token. Never retried, as every query would fail alike.
+    Unauthorized(String),
+    Request(String),
+    MalformedResponse(String),
+    /// A response without an `errors` array lacks this path, e.g. `data.MediaListCollection`.
+    MissingField(String),
+    /// Anilist rejected the query as too complex or too large.
+    QueryTooComplex(String),
+    /// The run took longer than `--max-runtime-secs` and was aborted.
+    RuntimeExceeded {
+        limit_secs: u64,
+    },
+    /// The columns of a list's scores don't line up, e.g. a column with fewer values
+    /// than there are titles.
+    DataFrame(String),
+    /// `--cache-only` found no global score for these ids.
+    NotCached(Vec<i64>),
+    /// The user's lists hold more entries than `--max-entries` allows.
+    TooManyEntries {
+        count: usize,
+        max: usize,
+    },
+}
+
+impl AnilistError {
+    /// Stable identifier for the error, used by `--error-format json`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AnilistError::UserNotFound(_) => "user_not_found",
+            AnilistError::RateLimited => "rate_limited",
+            AnilistError::Unauthorized(_) => "unauthorized",
+            AnilistError::Request(_) => "request",
+            AnilistError::MalformedResponse(_) => "malformed_response",
+            AnilistError::MissingField(_) => "missing_field",
+            AnilistError::QueryTooComplex(_) => "query_too_complex",
+            AnilistError::RuntimeExceeded { .. } => "runtime_exceeded",
+            AnilistError::DataFrame(_) => "dataframe",
+            AnilistError::NotCached(_) => "not_cached",
+            AnilistError::TooManyEntries { .. } => "too_many_entries",
+        }
+    }
+
+    /// Build an error from the `errors` array of a GraphQL response.
+    pub fn from_response(response: &Value) -> AnilistError {
+        let error = response
+            .get("errors")
+            .and_then(|value| value.as_array())
+            .and_then(|errors| errors.first());
+        let message = error
+            .and_then(|value| value.get("message"))
+            .and_then(|value| value.as_str())
+            .unwrap_or("Unexpected query response.")
+            .to_string();
+
+        match error
+            .and_then(|value| value.get("status"))
+            .and_then(|value| value.as_i64())
+        {
+            Some(404) => AnilistError::UserNotFound(message),
+            Some(401) => AnilistError::Unauthorized(message),
+            Some(400) if message.to_lowercase().contains("invalid token") => {
+                AnilistError::Unauthorized(message)
+            }
+            Some(429) => AnilistError::RateLimited,
+            Some(413) => AnilistError::QueryTooComplex(message),
+            _ if message.to_lowercase().contains("complexity") => {
+                AnilistError::QueryTooComplex(message)
+            }
+            _ => AnilistError::MalformedResponse(message),
+        }
+    }
+
+    /// The value at `path` of `response`. If a segment is missing, the error of the
+    /// response's `errors` array, or else a `MissingField` naming the path up to the
+    /// missing segment.
+    pub fn require_path<'a>(response: &'a Value, path: &[&str]) -> Result<&'a Value, AnilistError> {
+        let mut value = response;
+        for (depth, segment) in path.iter().enumerate() {
+            match value.get(segment) {
+                Some(next) if !next.is_null() => value = next,
+                _ if response.get("errors").is_some() => {
+                    return Err(AnilistError::from_response(response))
+                }
+                _ => return Err(AnilistError::MissingField(path[..=depth].join("."))),
+            }
+        }
+        Ok(value)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"kind": self.kind(), "message": self.to_string()})
+    }
+}
+
+impl fmt::Display for AnilistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnilistError::UserNotFound(msg) => write!(f, "User not found: {msg}"),
+            AnilistError::RateLimited => write!(f, "Rate limited by Anilist. Try again later."),
+            AnilistError::Unauthorized(msg) => write!(f, "Token rejected by Anilist: {msg}"),
+            AnilistError::Request(msg) => write!(f, "Query failed: {msg}"),
+            AnilistError::MalformedResponse(msg) => write!(f, "Malformed query response: {msg}"),
+            AnilistError::MissingField(path) => write!(f, "Query response missing {path}"),
+            AnilistError::QueryTooComplex(msg) => write!(f, "Query too complex: {msg}"),
+            AnilistError::RuntimeExceeded { limit_secs } => write!(
+                f,
+                "Run took longer than the --max-runtime-secs limit of {limit_secs}s and was aborted."
+            ),
+            AnilistError::DataFrame(msg) => {
+                write!(f, "Unable to build the scores dataframe: {msg}")
+            }
+            AnilistError::NotCached(ids) => {
+                let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                write!(
+                    f,
+                    "Global scores of {} titles are not cached: {}. Run without --cache-only to refresh them.",
+                    ids.len(),
+                    ids.join(", ")
+                )
+            }
+            AnilistError::TooManyEntries { count, max } => write!(
+                f,
+                "Lists hold {count} entries, over the --max-entries limit of {max}. Nothing was fetched."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AnilistError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn from_flag(value: &str) -> Option<ErrorFormat> {
+        match value.to_lowercase().as_str() {
+            "text" => Some(ErrorFormat::Text),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Print an error to stderr.
+    pub fn report(&self, err: &AnilistError) {
+        match self {
+            ErrorFormat::Text => eprintln!("{err}"),
+            ErrorFormat::Json => eprintln!("{}", err.to_json()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_segment_is_named() {
+        let response = json!({"data": {"MediaListCollection": null}});
+
+        let err = AnilistError::require_path(&response, &["data", "MediaListCollection", "lists"]);
+        assert_eq!(
+            err,
+            Err(AnilistError::MissingField(
+                "data.MediaListCollection".to_string()
+            ))
+        );
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Query response missing data.MediaListCollection"
+        );
+    }
+
+    #[test]
+    fn response_errors_take_precedence() {
+        let response = json!({
+            "errors": [{"message": "Not Found.", "status": 404}],
+            "data": {"MediaListCollection": null}
+        });
+
+        assert_eq!(
+            AnilistError::require_path(&response, &["data", "MediaListCollection"]),
+            Err(AnilistError::UserNotFound("Not Found.".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejected_tokens_are_unauthorized() {
+        for status in [400, 401] {
+            let response = json!({
+                "errors": [{"message": "Invalid token", "status": status}],
+                "data": null
+            });
+            assert_eq!(
+                AnilistError::from_response(&response),
+                AnilistError::Unauthorized("Invalid token".to_string())
+            );
+        }
+        let response = json!({"errors": [{"message": "Validation error", "status": 400}]});
+        assert!(matches!(
+            AnilistError::from_response(&response),
+            AnilistError::MalformedResponse(_)
+        ));
+    }
+
+    #[test]
+    fn complexity_errors_are_told_apart() {
+        let response = json!({
+            "errors": [{"message": "Max query complexity of 500 exceeded.", "status": 400}],
+            "data": null
+        });
+
+        assert!(matches!(
+            AnilistError::from_response(&response),
+            AnilistError::QueryTooComplex(_)
+        ));
+    }
+}
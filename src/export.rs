@@ -0,0 +1,278 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::media::TitleLanguage;
+use crate::responses::{self, MediaListCollection};
+use crate::{parse_entry_values, AnilistScores, InvalidScore, DEFAULT_LISTS};
+
+/// Scores of a list export downloaded from the Anilist settings page, one per default list.
+///
+/// `.xml` files are read as the MyAnimeList-style XML export, anything else as JSON in the
+/// shape of the `MediaListCollection` query response. Only user scores are available
+/// offline, so `global_avg_score` is left at 0.
+pub fn from_export(path: &str) -> Result<Vec<AnilistScores>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("Unable to read {path}: {err}"))?;
+    let is_xml = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"));
+
+    if is_xml {
+        Ok(parse_xml_export(&contents))
+    } else {
+        let export: Value = serde_json::from_str(&contents)
+            .map_err(|err| format!("Unable to parse {path} as json: {err}"))?;
+        parse_json_export(&export)
+    }
+}
+
+/// Value of the first `<tag>` in `block`, without any CDATA wrapper.
+fn tag_value<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let start = block.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + block[start..].find(&format!("</{tag}>"))?;
+    let value = block[start..end].trim();
+    Some(
+        value
+            .strip_prefix("<![CDATA[")
+            .and_then(|value| value.strip_suffix("]]>"))
+            .unwrap_or(value),
+    )
+}
+
+/// Contents of every `<tag>...</tag>` block in `xml`.
+fn tag_blocks<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut blocks = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let block = &rest[start + open.len()..];
+        let Some(end) = block.find(&close) else {
+            break;
+        };
+        blocks.push(&block[..end]);
+        rest = &block[end + close.len()..];
+    }
+    blocks
+}
+
+/// `MediaListStatus` of a `my_status` value of the XML export.
+fn media_list_status(status: &str) -> &'static str {
+    match status {
+        "Watching" | "Reading" => "CURRENT",
+        "Completed" => "COMPLETED",
+        "On-Hold" => "PAUSED",
+        "Dropped" => "DROPPED",
+        "Plan to Watch" | "Plan to Read" => "PLANNING",
+        _ => "",
+    }
+}
+
+fn empty_scores(list_type: &str, media_type: &str) -> AnilistScores {
+    AnilistScores {
+        list_type: list_type.to_string(),
+        media_type: media_type.to_string(),
+        anilist_id: vec![],
+        title: vec![],
+        user_score: vec![],
+        global_avg_score: vec![],
+        updated_at: vec![],
+        status: vec![],
+        notes: vec![],
+        repeat: vec![],
+        progress: vec![],
+        length: vec![],
+        airing_status: vec![],
+        release_year: vec![],
+        score_scale: None,
+        genres: vec![],
+        tags: vec![],
+    }
+}
+
+/// The XML export has one `<anime>` or `<manga>` block per title, with its list in
+/// `my_status`.
+pub fn parse_xml_export(xml: &str) -> Vec<AnilistScores> {
+    let mut lists: BTreeMap<String, AnilistScores> = BTreeMap::new();
+
+    for (media_type, id_tag, title_tag) in [
+        ("ANIME", "series_animedb_id", "series_title"),
+        ("MANGA", "manga_mangadb_id", "manga_title"),
+    ] {
+        for block in tag_blocks(xml, &media_type.to_lowercase()) {
+            let status = tag_value(block, "my_status").unwrap_or_default();
+            let id = tag_value(block, id_tag).and_then(|id| id.parse::<i64>().ok());
+            let (Some(id), true) = (id, DEFAULT_LISTS.contains(&status)) else {
+                continue;
+            };
+            let scores = lists
+                .entry(format!("{media_type}{status}"))
+                .or_insert_with(|| empty_scores(status, media_type));
+            scores.anilist_id.push(id);
+            scores
+                .title
+                .push(tag_value(block, title_tag).unwrap_or_default().to_string());
+            scores.user_score.push(
+                tag_value(block, "my_score")
+                    .and_then(|score| score.parse::<f64>().ok())
+                    .unwrap_or(0.0) as i64,
+            );
+            scores.global_avg_score.push(0);
+            scores.updated_at.push(0);
+            scores.status.push(media_list_status(status).to_string());
+            scores.release_year.push(0);
+            scores.progress.push(
+                tag_value(block, "my_watched_episodes")
+                    .or_else(|| tag_value(block, "my_read_chapters"))
+                    .and_then(|progress| progress.parse::<i64>().ok())
+                    .unwrap_or(0),
+            );
+            // 0 when the series is ongoing.
+            scores.length.push(
+                tag_value(block, "series_episodes")
+                    .or_else(|| tag_value(block, "manga_chapters"))
+                    .and_then(|length| length.parse::<i64>().ok())
+                    .filter(|length| *length > 0),
+            );
+            scores.repeat.push(
+                tag_value(block, "my_times_watched")
+                    .and_then(|repeat| repeat.parse::<i64>().ok())
+                    .unwrap_or(0),
+            );
+            scores.notes.push(
+                tag_value(block, "my_comments")
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+    }
+    lists.into_values().collect()
+}
+
+/// The JSON export mirrors `MediaListCollection`, optionally wrapped in `data`, with the
+/// media type in a top-level `type` field.
+pub fn parse_json_export(export: &Value) -> Result<Vec<AnilistScores>, String> {
+    let collection = export
+        .pointer("/data/MediaListCollection")
+        .unwrap_or(export);
+    let media_type = collection
+        .get("type")
+        .or_else(|| export.get("type"))
+        .and_then(|value| value.as_str())
+        .unwrap_or("ANIME")
+        .to_uppercase();
+    let collection: MediaListCollection =
+        responses::parse(collection, "MediaListCollection").map_err(|err| err.to_string())?;
+
+    let mut anilist_scores = vec![];
+    for list in collection.lists.iter() {
+        let list_type = list.name.as_str();
+        if !DEFAULT_LISTS.contains(&list_type) {
+            continue;
+        }
+        let mut entries = parse_entry_values(list, None);
+        if let Some(warning) = entries.handle_invalid_scores(InvalidScore::default(), list_type) {
+            eprintln!("{warning}");
+        }
+        let titles: BTreeMap<i64, String> = list
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let title = &entry.media.as_ref()?.title;
+                Some((entry.media_id?, title.preferred(TitleLanguage::Romaji)))
+            })
+            .collect();
+
+        anilist_scores.push(AnilistScores {
+            list_type: list_type.to_string(),
+            media_type: media_type.clone(),
+            title: entries
+                .anilist_id
+                .iter()
+                .map(|id| titles.get(id).cloned().unwrap_or_default())
+                .collect(),
+            global_avg_score: vec![0; entries.len()],
+            release_year: vec![0; entries.len()],
+            anilist_id: entries.anilist_id,
+            user_score: entries.user_score,
+            updated_at: entries.updated_at,
+            status: entries.status,
+            notes: entries.notes,
+            repeat: entries.repeat,
+            progress: entries.progress,
+            length: entries.length,
+            airing_status: vec![],
+            score_scale: entries.score_format.map(|_| 1),
+            genres: vec![],
+            tags: vec![],
+        });
+    }
+    Ok(anilist_scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn xml_export_is_grouped_by_status() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>
+<myanimelist>
+  <myinfo><user_export_type>1</user_export_type></myinfo>
+  <anime>
+    <series_animedb_id>457</series_animedb_id>
+    <series_title><![CDATA[Mushishi]]></series_title>
+    <my_score>9</my_score>
+    <my_status>Completed</my_status>
+  </anime>
+  <anime>
+    <series_animedb_id>387</series_animedb_id>
+    <series_title><![CDATA[Haibane Renmei]]></series_title>
+    <my_score>8</my_score>
+    <my_status>Watching</my_status>
+    <my_comments><![CDATA[Rewatch, someday]]></my_comments>
+  </anime>
+  <anime>
+    <series_animedb_id>1</series_animedb_id>
+    <my_score>0</my_score>
+    <my_status>Plan to Watch</my_status>
+  </anime>
+</myanimelist>";
+
+        let lists = parse_xml_export(xml);
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].list_type, "Completed");
+        assert_eq!(lists[0].anilist_id, vec![457]);
+        assert_eq!(lists[0].title, vec!["Mushishi"]);
+        assert_eq!(lists[0].user_score, vec![9]);
+        assert_eq!(lists[1].list_type, "Watching");
+        assert_eq!(lists[1].global_avg_score, vec![0]);
+        assert_eq!(lists[1].status, vec!["CURRENT"]);
+        assert_eq!(lists[1].notes, vec!["Rewatch, someday"]);
+    }
+
+    #[test]
+    fn json_export_reads_default_lists() {
+        let export = json!({
+            "type": "MANGA",
+            "lists": [
+                {"name": "Completed", "entries": [
+                    {"mediaId": 30013, "score": 85, "updatedAt": 1650000000,
+                     "media": {"title": {"romaji": "Yokohama Kaidashi Kikou"}}},
+                    {"mediaId": 30002, "score": 70}
+                ]},
+                {"name": "Dropped", "entries": [{"mediaId": 1, "score": 20}]}
+            ]
+        });
+
+        let lists = parse_json_export(&export).unwrap();
+        assert_eq!(lists.len(), 1);
+        assert_eq!(lists[0].media_type, "MANGA");
+        assert_eq!(lists[0].anilist_id, vec![30013, 30002]);
+        assert_eq!(lists[0].title, vec!["Yokohama Kaidashi Kikou", ""]);
+        assert_eq!(lists[0].user_score, vec![85, 70]);
+        assert_eq!(lists[0].updated_at, vec![1650000000, 0]);
+    }
+}
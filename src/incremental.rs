@@ -0,0 +1,406 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::media::{MediaDetails, MediaTitle};
+use crate::responses::MediaTag;
+use crate::writer::DisplayScale;
+
+/// What a previous run saved with `--format json` recorded for a title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreviousEntry {
+    pub updated_at: i64,
+    pub global_avg_score: i64,
+    pub title: String,
+    /// 0 if the run predates the `release_year` column.
+    pub release_year: i64,
+    /// Empty if the run predates them being saved, like `tags` and `airing_status`.
+    pub genres: Vec<String>,
+    pub tags: Vec<(String, i64)>,
+    pub airing_status: String,
+}
+
+impl PreviousEntry {
+    pub fn details(&self) -> MediaDetails {
+        MediaDetails {
+            global_score: self.global_avg_score,
+            // Already resolved to the preferred language, so every language falls back to it.
+            title: MediaTitle {
+                romaji: Some(self.title.clone()),
+                ..Default::default()
+            },
+            genres: self.genres.clone(),
+            tags: self.tags.clone(),
+            year: self.release_year,
+            airing_status: self.airing_status.clone(),
+        }
+    }
+}
+
+/// A row of a run saved with `--format json`. Columns dropped by `--compact`, or missing
+/// from older runs, read as None or empty.
+#[derive(Debug, Deserialize)]
+struct PreviousRow {
+    anilist_id: Option<i64>,
+    updated_at: Option<i64>,
+    /// An integer out of 100, or a decimal out of 10 with `--display-scale 10`.
+    global_avg_score: Option<Value>,
+    title: Option<String>,
+    release_year: Option<i64>,
+    #[serde(default)]
+    genres: Vec<String>,
+    #[serde(default)]
+    tags: Vec<MediaTag>,
+    airing_status: Option<String>,
+}
+
+/// A score written on `scale`, back on the 100-point scale. None if it was written on
+/// the other scale: integers out of 100, or decimals out of 10.
+fn point_100_score(value: &Value, scale: DisplayScale) -> Option<i64> {
+    match scale {
+        DisplayScale::Hundred => value.as_i64(),
+        DisplayScale::Ten if value.is_f64() => value.as_f64().map(|s| (s * 10.0).round() as i64),
+        DisplayScale::Ten => None,
+    }
+}
+
+/// Titles of a previous run saved with `--format json` on `scale`, keyed by id.
+pub fn load_previous_run(
+    fname: &str,
+    scale: DisplayScale,
+) -> Result<HashMap<i64, PreviousEntry>, String> {
+    let contents =
+        fs::read_to_string(fname).map_err(|err| format!("Unable to read {fname}: {err}"))?;
+    let rows: Vec<PreviousRow> = serde_json::from_str(&contents)
+        .map_err(|err| format!("Unable to parse {fname} as a json run: {err}"))?;
+
+    let mut previous = HashMap::new();
+    for row in rows {
+        let global_avg_score = match &row.global_avg_score {
+            Some(value) => Some(point_100_score(value, scale).ok_or_else(|| {
+                format!("{fname} was written with another --display-scale. Use the same one.")
+            })?),
+            None => None,
+        };
+        if let (Some(id), Some(updated_at), Some(global_avg_score)) =
+            (row.anilist_id, row.updated_at, global_avg_score)
+        {
+            previous.insert(
+                id,
+                PreviousEntry {
+                    updated_at,
+                    global_avg_score,
+                    title: row.title.unwrap_or_default(),
+                    release_year: row.release_year.unwrap_or(0),
+                    genres: row.genres,
+                    tags: row
+                        .tags
+                        .into_iter()
+                        .map(|tag| (tag.name, tag.rank.unwrap_or(0)))
+                        .collect(),
+                    airing_status: row.airing_status.unwrap_or_default(),
+                },
+            );
+        }
+    }
+    Ok(previous)
+}
+
+/// Previous runs of every list of `username` saved with `--format json` in `dir`, keyed
+/// by list name, whichever lists they were. Empty if `dir` doesn't exist yet.
+pub fn load_previous_runs(
+    dir: &Path,
+    media_type: &str,
+    username: &str,
+    scale: DisplayScale,
+) -> Result<HashMap<String, HashMap<i64, PreviousEntry>>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(format!("Unable to read {}: {err}", dir.display())),
+    };
+    let prefix = format!("anilist_{media_type}_");
+    let suffix = format!("_score_{username}.json");
+    let mut previous_runs = HashMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|err| format!("Unable to read {}: {err}", dir.display()))?
+            .path();
+        let fname = path.file_name().unwrap_or_default().to_string_lossy();
+        let list_type = fname
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix));
+        if let Some(list_type) = list_type.filter(|list_type| !list_type.is_empty()) {
+            let previous = load_previous_run(&path.to_string_lossy(), scale)?;
+            previous_runs.insert(list_type.to_string(), previous);
+        }
+    }
+    Ok(previous_runs)
+}
+
+/// Ids of the titles that are new or were updated since the previous run.
+pub fn stale_ids(
+    anilist_id: &[i64],
+    updated_at: &[i64],
+    previous: &HashMap<i64, PreviousEntry>,
+) -> Vec<i64> {
+    anilist_id
+        .iter()
+        .zip(updated_at.iter())
+        .filter(|(id, updated_at)| match previous.get(id) {
+            Some(entry) => entry.updated_at != **updated_at || **updated_at == 0,
+            None => true,
+        })
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// Ids of the titles `previous` has no global score for, all of them if there was no
+/// previous run.
+pub fn uncached_ids(
+    anilist_id: &[i64],
+    previous: Option<&HashMap<i64, PreviousEntry>>,
+) -> Vec<i64> {
+    anilist_id
+        .iter()
+        .filter(|id| previous.is_none_or(|previous| !previous.contains_key(id)))
+        .copied()
+        .collect()
+}
+
+/// `fetched` details, completed with the previous run's for every title not fetched.
+pub fn merge_details(
+    mut fetched: HashMap<i64, MediaDetails>,
+    previous: &HashMap<i64, PreviousEntry>,
+) -> HashMap<i64, MediaDetails> {
+    for (id, entry) in previous.iter() {
+        fetched.entry(*id).or_insert_with(|| entry.details());
+    }
+    fetched
+}
+
+/// Overall average-ness saved by `--track-ratio` at the end of a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatioSnapshot {
+    /// Unix timestamp of the run.
+    pub run_at: i64,
+    pub ratio: f64,
+    /// Conformity index of the run. Absent from snapshots saved before it was tracked.
+    #[serde(default)]
+    pub conformity: Option<f64>,
+}
+
+/// Snapshot saved by the previous `--track-ratio` run, None if there was none.
+pub fn load_ratio_snapshot(fname: &str) -> Result<Option<RatioSnapshot>, String> {
+    let contents = match fs::read_to_string(fname) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Unable to read {fname}: {err}")),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|err| format!("Unable to parse {fname} as json: {err}"))
+}
+
+pub fn save_ratio_snapshot(fname: &str, snapshot: &RatioSnapshot) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(snapshot)
+        .map_err(|err| format!("Unable to serialize {fname}: {err}"))?;
+    fs::write(fname, contents).map_err(|err| format!("Unable to write {fname}: {err}"))
+}
+
+/// Whether taste moved away from the global average (1.0) between two ratios. None if
+/// it is as far as before.
+pub fn more_contrarian(previous: f64, current: f64) -> Option<bool> {
+    let (previous, current) = ((previous - 1.0).abs(), (current - 1.0).abs());
+    if current == previous {
+        None
+    } else {
+        Some(current > previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::TitleLanguage;
+    use crate::writer::{JsonScoreWriter, ScoreWriter, WriterOptions};
+    use crate::AnilistScores;
+
+    fn previous_run() -> HashMap<i64, PreviousEntry> {
+        HashMap::from([
+            (
+                1,
+                PreviousEntry {
+                    updated_at: 100,
+                    global_avg_score: 75,
+                    title: "Mushishi".to_string(),
+                    release_year: 2005,
+                    ..Default::default()
+                },
+            ),
+            (
+                2,
+                PreviousEntry {
+                    updated_at: 200,
+                    global_avg_score: 82,
+                    title: "Haibane Renmei".to_string(),
+                    release_year: 2002,
+                    ..Default::default()
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn ratio_snapshot_round_trips() {
+        let fname = std::env::temp_dir()
+            .join(format!("anilist_ratio_{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(load_ratio_snapshot(&fname), Ok(None));
+
+        let snapshot = RatioSnapshot {
+            run_at: 1_700_000_000,
+            ratio: 1.04,
+            conformity: Some(8.5),
+        };
+        save_ratio_snapshot(&fname, &snapshot).unwrap();
+        assert_eq!(load_ratio_snapshot(&fname), Ok(Some(snapshot)));
+        fs::remove_file(&fname).unwrap();
+        let old: RatioSnapshot =
+            serde_json::from_str(r#"{"run_at": 1600000000, "ratio": 0.98}"#).unwrap();
+        assert_eq!(old.conformity, None);
+
+        assert_eq!(more_contrarian(1.04, 0.9), Some(true));
+        assert_eq!(more_contrarian(0.9, 1.02), Some(false));
+        assert_eq!(more_contrarian(0.95, 1.05), None);
+    }
+
+    #[test]
+    fn scores_out_of_ten_are_rescaled() {
+        let fname = std::env::temp_dir()
+            .join(format!("anilist_previous_{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        fs::write(
+            &fname,
+            r#"[{"anilist_id": 457, "updated_at": 100, "global_avg_score": 8.6, "title": "Mushishi"},
+                {"anilist_id": 387, "updated_at": 200, "global_avg_score": null}]"#,
+        )
+        .unwrap();
+
+        let previous = load_previous_run(&fname, DisplayScale::Ten).unwrap();
+        assert_eq!(previous.len(), 1);
+        assert_eq!(previous[&457].global_avg_score, 86);
+        let err = load_previous_run(&fname, DisplayScale::Hundred).unwrap_err();
+        assert!(err.contains("--display-scale"), "{err}");
+        fs::remove_file(&fname).unwrap();
+    }
+
+    #[test]
+    fn details_survive_a_json_round_trip() {
+        let scores = AnilistScores {
+            anilist_id: vec![457],
+            updated_at: vec![100],
+            release_year: vec![2005],
+            genres: vec![vec!["Mystery".to_string(), "Slice of Life".to_string()]],
+            tags: vec![vec![("Iyashikei".to_string(), 94)]],
+            airing_status: vec!["FINISHED".to_string()],
+            ..AnilistScores::fixture(vec![90], vec![86]).with_titles(&["Mushishi"])
+        };
+        let mut json = vec![];
+        JsonScoreWriter {
+            options: WriterOptions::default(),
+        }
+        .write(&scores, &mut json)
+        .unwrap();
+        let fname = std::env::temp_dir()
+            .join(format!("anilist_details_{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        fs::write(&fname, json).unwrap();
+
+        let previous = load_previous_run(&fname, DisplayScale::Hundred).unwrap();
+        fs::remove_file(&fname).unwrap();
+        let details = previous[&457].details();
+        assert_eq!(details.genres, scores.genres[0]);
+        assert_eq!(details.tags, scores.tags[0]);
+        assert_eq!(details.airing_status, "FINISHED");
+        assert_eq!(details.year, 2005);
+    }
+
+    #[test]
+    fn previous_runs_are_found_for_every_list() {
+        let dir = std::env::temp_dir().join(format!("anilist_runs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let run = r#"[{"anilist_id": 457, "updated_at": 100, "global_avg_score": 86}]"#;
+        for fname in [
+            "anilist_ANIME_Completed_score_koisland.json",
+            "anilist_ANIME_Guilty pleasures_score_koisland.json",
+            "anilist_ANIME_Completed_score_someone.json",
+            "anilist_MANGA_Completed_score_koisland.json",
+            "anilist_ANIME_Completed_score_koisland.csv",
+        ] {
+            fs::write(dir.join(fname), run).unwrap();
+        }
+
+        let previous_runs =
+            load_previous_runs(&dir, "ANIME", "koisland", DisplayScale::Hundred).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let mut lists: Vec<&str> = previous_runs.keys().map(String::as_str).collect();
+        lists.sort_unstable();
+        assert_eq!(lists, vec!["Completed", "Guilty pleasures"]);
+        assert_eq!(previous_runs["Guilty pleasures"][&457].global_avg_score, 86);
+        assert_eq!(
+            load_previous_runs(&dir, "ANIME", "koisland", DisplayScale::Hundred),
+            Ok(HashMap::new())
+        );
+    }
+
+    #[test]
+    fn nothing_changed_needs_no_fetch() {
+        let previous = previous_run();
+        let stale = stale_ids(&[1, 2], &[100, 200], &previous);
+
+        assert!(stale.is_empty());
+
+        let details = merge_details(HashMap::new(), &previous);
+        assert_eq!(details[&1].global_score, 75);
+        assert_eq!(details[&2].global_score, 82);
+        assert_eq!(
+            details[&2].title.preferred(TitleLanguage::English),
+            "Haibane Renmei"
+        );
+    }
+
+    #[test]
+    fn new_and_updated_titles_are_stale() {
+        let previous = previous_run();
+        let stale = stale_ids(&[1, 2, 3], &[100, 250, 300], &previous);
+
+        assert_eq!(stale, vec![2, 3]);
+
+        let fetched = HashMap::from([
+            (
+                2,
+                MediaDetails {
+                    global_score: 83,
+                    ..Default::default()
+                },
+            ),
+            (
+                3,
+                MediaDetails {
+                    global_score: 60,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let details = merge_details(fetched, &previous);
+        assert_eq!(details[&1].global_score, 75);
+        assert_eq!(details[&2].global_score, 83);
+        assert_eq!(details[&3].global_score, 60);
+    }
+}
@@ -0,0 +1,3736 @@
+use chrono::{TimeZone, Utc};
+use polars::prelude::{df, CsvWriter, DataFrame, NamedFrom, SerWriter, Series};
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env::{self, args};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod bias;
+pub mod cli;
+pub mod client;
+pub mod compare;
+pub mod diff;
+pub mod engagement;
+pub mod error;
+pub mod export;
+pub mod incremental;
+pub mod media;
+pub mod pretty;
+pub mod queries;
+pub mod rescore;
+pub mod responses;
+pub mod sqlite;
+pub mod stats;
+pub mod stream;
+pub mod writer;
+
+use bias::{bias_dataframe, genre_bias, most_contrarian, tag_bias, CONTRARIAN_TAGS};
+use clap::Parser;
+use cli::{CheckArgs, Cli, Command, CompareArgs, FetchArgs, RescoreArgs};
+use client::{AnilistClient, Deadline, HealthCheck, RetryPolicy, Transport};
+use engagement::{engagement_counts, engagement_dataframe, STATUS_CHANGE_LISTS};
+use error::{AnilistError, ErrorFormat};
+use futures_util::StreamExt;
+use incremental::{
+    load_previous_runs, load_ratio_snapshot, merge_details, more_contrarian, save_ratio_snapshot,
+    stale_ids, uncached_ids, PreviousEntry, RatioSnapshot,
+};
+use media::{MediaDetails, TitleLanguage};
+use queries::{
+    avg_score_variables, build_avg_score_query, build_favourites_query, build_status_counts_query,
+    estimate_avg_score_complexity, MAX_QUERY_COMPLEXITY, QUERY_USER_LISTS, QUERY_USER_MEDIA_SCORE,
+    QUERY_USER_STATISTICS,
+};
+use responses::{FavouritesPage, Media, MediaList, StatusCount, UserStats};
+use writer::{parse_columns_order, writer_for_format, CsvScoreWriter, ScoreWriter, WriterOptions};
+
+/// Values of Anilist's `MediaListStatus`.
+pub const MEDIA_LIST_STATUSES: &[&str] = &[
+    "CURRENT",
+    "PLANNING",
+    "COMPLETED",
+    "DROPPED",
+    "PAUSED",
+    "REPEATING",
+];
+
+/// Names of a comma-separated `--exclude-lists` value, trimmed and lowercased for
+/// `is_excluded`. Empty names are dropped.
+pub fn parse_list_names(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Whether `list_type` is one of the `excluded` names, ignoring case and surrounding
+/// whitespace.
+pub fn is_excluded(list_type: &str, excluded: &[String]) -> bool {
+    let list_type = list_type.trim().to_lowercase();
+    excluded.contains(&list_type)
+}
+
+/// Validate a comma-separated `--status` value against `MEDIA_LIST_STATUSES`.
+pub fn parse_statuses(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|status| status.trim().to_uppercase())
+        .filter(|status| !status.is_empty())
+        .map(|status| {
+            if MEDIA_LIST_STATUSES.contains(&status.as_str()) {
+                Ok(status)
+            } else {
+                Err(format!(
+                    "Unknown status '{status}'. Expected one of {}.",
+                    MEDIA_LIST_STATUSES.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Warn when fewer than this share of the user's entries were fetched, unless
+/// `--warn-threshold` overrides it.
+const DEFAULT_WARN_THRESHOLD: f64 = 0.5;
+
+/// Lists whose entries are scored.
+pub const DEFAULT_LISTS: &[&str] = &["Watching", "Completed"];
+/// `MediaListStatus` of the entries of the standard list `list_type`, ignoring case. None
+/// for custom lists.
+pub fn list_status(list_type: &str) -> Option<&'static str> {
+    match list_type.trim().to_lowercase().as_str() {
+        "watching" | "reading" => Some("CURRENT"),
+        "completed" => Some("COMPLETED"),
+        "planning" => Some("PLANNING"),
+        "dropped" => Some("DROPPED"),
+        "paused" => Some("PAUSED"),
+        "rewatching" | "rereading" => Some("REPEATING"),
+        _ => None,
+    }
+}
+
+/// List of titles the user intends to start. Its entries are never scored.
+pub const PLANNING_LIST: &str = "Planning";
+/// `MediaStatus` of titles dropped by `--exclude-unreleased`.
+const NOT_YET_RELEASED: &str = "NOT_YET_RELEASED";
+/// Highest rated titles listed by `--planning-report`.
+const PLANNING_TOP_TITLES: usize = 5;
+
+#[derive(Debug, Clone, Default)]
+pub struct AnilistScores {
+    pub list_type: String,
+    /// ANIME or MANGA.
+    pub media_type: String,
+    pub anilist_id: Vec<i64>,
+    pub title: Vec<String>,
+    pub user_score: Vec<i64>,
+    pub global_avg_score: Vec<i64>,
+    /// Unix timestamp of the last update to each entry. 0 if unknown.
+    pub updated_at: Vec<i64>,
+    /// `MediaListStatus` of each entry, e.g. COMPLETED. Empty if unknown.
+    pub status: Vec<String>,
+    /// Factor bringing `user_score` onto the 100-point scale, when the score format is
+    /// known. Detected from the scores if None.
+    pub score_scale: Option<i64>,
+    /// Free-text notes of each entry, empty if none.
+    pub notes: Vec<String>,
+    /// Year each title was released, 0 if unknown.
+    pub release_year: Vec<i64>,
+    /// Genres of each title. Empty when they were not fetched, e.g. from an export.
+    pub genres: Vec<Vec<String>>,
+    /// Tags of each title with their rank. Empty when they were not fetched.
+    pub tags: Vec<Vec<(String, i64)>>,
+    /// Times each title was rewatched or reread, 0 if unknown.
+    pub repeat: Vec<i64>,
+    /// Episodes watched or chapters read of each title. Empty when not requested.
+    pub progress: Vec<i64>,
+    /// Episodes or chapters of each title, None if unknown, e.g. for ongoing series.
+    /// Empty when progress was not requested.
+    pub length: Vec<Option<i64>>,
+    /// `MediaStatus` of each title, e.g. RELEASING, empty if unknown. Empty when global
+    /// scores were not fetched.
+    pub airing_status: Vec<String>,
+}
+
+#[cfg(test)]
+impl AnilistScores {
+    /// Completed anime list scored `user_score` against `global_avg_score`, with ids from 1
+    /// and every other per-title column at its empty value. Tests change the rest with
+    /// struct update syntax.
+    pub(crate) fn fixture(user_score: Vec<i64>, global_avg_score: Vec<i64>) -> AnilistScores {
+        let len = user_score.len();
+        AnilistScores {
+            list_type: "Completed".to_string(),
+            media_type: "ANIME".to_string(),
+            anilist_id: (1..=len as i64).collect(),
+            title: vec![String::new(); len],
+            user_score,
+            global_avg_score,
+            updated_at: vec![0; len],
+            status: vec!["COMPLETED".to_string(); len],
+            notes: vec![String::new(); len],
+            repeat: vec![0; len],
+            progress: vec![0; len],
+            length: vec![None; len],
+            release_year: vec![0; len],
+            ..Default::default()
+        }
+    }
+
+    /// This list with `titles`, one per title.
+    pub(crate) fn with_titles(mut self, titles: &[&str]) -> AnilistScores {
+        self.title = titles.iter().map(|title| title.to_string()).collect();
+        self
+    }
+}
+
+/// A single title of `AnilistScores`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoreEntry {
+    pub anilist_id: i64,
+    pub title: String,
+    pub user_score: i64,
+    pub global_avg_score: i64,
+    pub updated_at: i64,
+    pub status: String,
+}
+
+/// Entries parsed from a single media list.
+#[derive(Debug, Clone, Default)]
+pub struct ListEntries {
+    pub anilist_id: Vec<i64>,
+    pub user_score: Vec<i64>,
+    pub updated_at: Vec<i64>,
+    /// Whether each entry is hidden from other users.
+    pub private: Vec<bool>,
+    pub status: Vec<String>,
+    /// Free-text notes of each entry. Empty if none or not requested.
+    pub notes: Vec<String>,
+    /// Times each entry was rewatched or reread.
+    pub repeat: Vec<i64>,
+    /// Episodes watched or chapters read of each entry. 0 if not requested.
+    pub progress: Vec<i64>,
+    /// Episodes or chapters of each entry's title, None if unknown or not requested.
+    pub length: Vec<Option<i64>>,
+    /// Format the scores were converted to the 100-point scale from, if they were.
+    pub score_format: Option<ScoreFormat>,
+}
+
+impl ListEntries {
+    pub fn len(&self) -> usize {
+        self.anilist_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anilist_id.is_empty()
+    }
+
+    /// Keep only the entries at `indices`, in that order.
+    fn select(&mut self, indices: &[usize]) {
+        self.anilist_id = indices.iter().map(|i| self.anilist_id[*i]).collect();
+        self.user_score = indices.iter().map(|i| self.user_score[*i]).collect();
+        self.updated_at = indices.iter().map(|i| self.updated_at[*i]).collect();
+        self.private = indices.iter().map(|i| self.private[*i]).collect();
+        self.status = indices.iter().map(|i| self.status[*i].clone()).collect();
+        self.notes = indices.iter().map(|i| self.notes[*i].clone()).collect();
+        self.repeat = indices.iter().map(|i| self.repeat[*i]).collect();
+        self.progress = indices.iter().map(|i| self.progress[*i]).collect();
+        self.length = indices.iter().map(|i| self.length[*i]).collect();
+    }
+
+    /// Consecutive runs of up to `size` entries, in order.
+    pub fn chunks(&self, size: usize) -> Vec<ListEntries> {
+        (0..self.len())
+            .step_by(size.max(1))
+            .map(|start| {
+                let mut chunk = self.clone();
+                let indices: Vec<usize> = (start..(start + size).min(self.len())).collect();
+                chunk.select(&indices);
+                chunk
+            })
+            .collect()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        let indices: Vec<usize> = (0..len.min(self.len())).collect();
+        self.select(&indices);
+    }
+
+    /// Randomly keep `amount` entries, preserving their original order.
+    pub fn sample(&mut self, amount: usize, rng: &mut StdRng) {
+        let mut indices = index::sample(rng, self.len(), amount.min(self.len())).into_vec();
+        indices.sort_unstable();
+        self.select(&indices);
+    }
+
+    /// Keep only the entries whose status is one of `statuses`.
+    pub fn with_status(&mut self, statuses: &[String]) {
+        let indices: Vec<usize> = (0..self.len())
+            .filter(|i| statuses.contains(&self.status[*i]))
+            .collect();
+        self.select(&indices);
+    }
+
+    /// Apply `policy` to the scores outside 0 to 100, returning a warning naming their
+    /// ids if there are any.
+    pub fn handle_invalid_scores(
+        &mut self,
+        policy: InvalidScore,
+        list_type: &str,
+    ) -> Option<String> {
+        let invalid: Vec<usize> = (0..self.len())
+            .filter(|i| !(0..=100).contains(&self.user_score[*i]))
+            .collect();
+        if invalid.is_empty() {
+            return None;
+        }
+        let ids: Vec<String> = invalid
+            .iter()
+            .map(|i| self.anilist_id[*i].to_string())
+            .collect();
+        let action = match policy {
+            InvalidScore::Clamp => {
+                for i in invalid.iter() {
+                    self.user_score[*i] = self.user_score[*i].clamp(0, 100);
+                }
+                "Clamped them to the scale"
+            }
+            InvalidScore::Skip => {
+                let indices: Vec<usize> =
+                    (0..self.len()).filter(|i| !invalid.contains(i)).collect();
+                self.select(&indices);
+                "Skipped them"
+            }
+            InvalidScore::Keep => "Kept them as is",
+        };
+        Some(format!(
+            "Warning: {} scores in '{list_type}' are outside 0 to 100 (ids {}). {action}, see --on-invalid-score.",
+            ids.len(),
+            ids.join(", ")
+        ))
+    }
+
+    pub fn exclude_private(&mut self) {
+        let indices: Vec<usize> = (0..self.len()).filter(|i| !self.private[*i]).collect();
+        self.select(&indices);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendBucket {
+    Year,
+    Month,
+}
+
+impl TrendBucket {
+    pub fn from_flag(value: &str) -> Option<TrendBucket> {
+        match value.to_lowercase().as_str() {
+            "year" => Some(TrendBucket::Year),
+            "month" => Some(TrendBucket::Month),
+            _ => None,
+        }
+    }
+
+    fn label(&self, timestamp: i64) -> Option<String> {
+        let updated = Utc.timestamp_opt(timestamp, 0).single()?;
+        match self {
+            TrendBucket::Year => Some(updated.format("%Y").to_string()),
+            TrendBucket::Month => Some(updated.format("%Y-%m").to_string()),
+        }
+    }
+}
+
+/// Decimal places of printed summaries unless `--precision` overrides it.
+const DEFAULT_PRECISION: usize = 3;
+
+/// `value` rounded to `precision` decimal places, signed if `signed`.
+pub fn format_float(value: f64, precision: usize, signed: bool) -> String {
+    if signed {
+        format!("{value:+.precision$}")
+    } else {
+        format!("{value:.precision$}")
+    }
+}
+
+/// Pearson correlation of `pairs`. None if fewer than two or either side never varies.
+pub fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs.iter() {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        None
+    } else {
+        Some(cov / (var_x * var_y).sqrt())
+    }
+}
+
+fn mean(values: &[i64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<i64>() as f64 / values.len() as f64
+    }
+}
+
+/// Average-ness over the titles of every list on `basis`.
+pub fn overall_ratio(anilist_scores: &[AnilistScores], basis: RatioBasis) -> f64 {
+    let user: Vec<i64> = anilist_scores
+        .iter()
+        .flat_map(|score| score.user_score.iter().copied())
+        .collect();
+    let global: Vec<i64> = anilist_scores
+        .iter()
+        .flat_map(|score| score.global_avg_score.iter().copied())
+        .collect();
+    basis.ratio(&user, &global)
+}
+
+/// Which of two media types and their overall ratios is further from the global average,
+/// None if neither is, e.g. when one has no ratio.
+pub fn more_contrarian_media<'a>(first: (&'a str, f64), second: (&'a str, f64)) -> Option<&'a str> {
+    if !first.1.is_finite() || !second.1.is_finite() {
+        return None;
+    }
+    match more_contrarian(first.1, second.1)? {
+        true => Some(second.0),
+        false => Some(first.0),
+    }
+}
+
+/// Conformity index over the titles of every list, see `stats::conformity_index`.
+pub fn overall_conformity(anilist_scores: &[AnilistScores]) -> Option<f64> {
+    let deltas: Vec<i64> = anilist_scores
+        .iter()
+        .flat_map(|score| score.deltas())
+        .flatten()
+        .collect();
+    stats::conformity_index(&deltas)
+}
+
+/// One row per list with its entry count, mean scores, average-ness ratio and conformity
+/// index.
+pub fn summary_dataframe(
+    anilist_scores: &[AnilistScores],
+    media: &str,
+) -> Result<DataFrame, String> {
+    let df = df!(
+        "list_type" => anilist_scores.iter().map(|score| score.list_type.clone()).collect::<Vec<String>>(),
+        "media_type" => vec![media.to_string(); anilist_scores.len()],
+        "entry_count" => anilist_scores.iter().map(|score| score.anilist_id.len() as i64).collect::<Vec<i64>>(),
+        "mean_user_score" => anilist_scores.iter().map(AnilistScores::mean_user_score).collect::<Vec<f64>>(),
+        "mean_global_score" => anilist_scores.iter().map(AnilistScores::mean_global_score).collect::<Vec<f64>>(),
+        "ratio" => anilist_scores.iter().map(AnilistScores::ratio).collect::<Vec<f64>>(),
+        "conformity" => anilist_scores.iter().map(|score| overall_conformity(std::slice::from_ref(score))).collect::<Vec<Option<f64>>>()
+    );
+    df.map_err(|err| format!("Unable to save Anilist summary to dataframe: {:?}", err))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScoreComparison {
+    pub above: usize,
+    pub below: usize,
+    pub equal: usize,
+}
+
+impl ScoreComparison {
+    pub fn total(&self) -> usize {
+        self.above + self.below + self.equal
+    }
+
+    pub fn percent(&self, count: usize) -> f64 {
+        if self.total() == 0 {
+            0.0
+        } else {
+            count as f64 / self.total() as f64 * 100.0
+        }
+    }
+}
+
+impl AnilistScores {
+    /// Columns of the DataFrame with their lengths, which must all match `anilist_id`.
+    fn column_lengths(&self) -> [(&'static str, usize); 7] {
+        [
+            ("title", self.title.len()),
+            ("user_score", self.user_score.len()),
+            ("global_avg_score", self.global_avg_score.len()),
+            ("updated_at", self.updated_at.len()),
+            ("status", self.status.len()),
+            ("release_year", self.release_year.len()),
+            ("anilist_id", self.anilist_id.len()),
+        ]
+    }
+
+    /// One row per title. Fails naming the first column whose length differs from
+    /// `anilist_id`'s, which is a bug in whatever built these scores.
+    pub fn as_dataframe(&self) -> Result<DataFrame, AnilistError> {
+        let expected = self.anilist_id.len();
+        if let Some((column, len)) = self
+            .column_lengths()
+            .into_iter()
+            .find(|(_, len)| *len != expected)
+        {
+            return Err(AnilistError::DataFrame(format!(
+                "column {column} of '{}' has {len} values, but anilist_id has {expected}",
+                self.list_type
+            )));
+        }
+        df!(
+            "list_type" => vec![self.list_type.clone(); expected],
+            "anilist_id" => self.anilist_id.clone(),
+            "title" => self.title.clone(),
+            "user_score" => self.user_score.clone(),
+            "global_avg_score" => self.global_avg_score.clone(),
+            "updated_at" => self.updated_at.clone(),
+            "status" => self.status.clone(),
+            "release_year" => self.release_year.clone()
+        )
+        .map_err(|err| AnilistError::DataFrame(err.to_string()))
+    }
+
+    pub fn entries(&self) -> Vec<ScoreEntry> {
+        (0..self.anilist_id.len())
+            .map(|i| ScoreEntry {
+                anilist_id: self.anilist_id[i],
+                title: self.title[i].clone(),
+                user_score: self.user_score[i],
+                global_avg_score: self.global_avg_score[i],
+                updated_at: self.updated_at[i],
+                status: self.status[i].clone(),
+            })
+            .collect()
+    }
+
+    /// Anilist page of each title.
+    pub fn urls(&self) -> Vec<String> {
+        let media = self.media_type.to_lowercase();
+        self.anilist_id
+            .iter()
+            .map(|id| format!("https://anilist.co/{media}/{id}"))
+            .collect()
+    }
+
+    pub fn mean_user_score(&self) -> f64 {
+        mean(&self.user_score)
+    }
+
+    pub fn mean_global_score(&self) -> f64 {
+        mean(&self.global_avg_score)
+    }
+
+    /// Warning if the list has titles but none came back with a global score, which only
+    /// happens when the average score query is malformed or rejected, not for real data.
+    /// Its ratio would be infinite or NaN.
+    pub fn missing_global_warning(&self) -> Option<String> {
+        if self.global_avg_score.is_empty() || self.global_avg_score.iter().any(|score| *score != 0)
+        {
+            return None;
+        }
+        Some(format!(
+            "Warning: none of the {} titles of '{}' have a global score. The average score \
+             query may be malformed or unauthorized; skipping its average-ness.",
+            self.global_avg_score.len(),
+            self.list_type
+        ))
+    }
+
+    /// Average-ness score: sum of user scores over sum of global average scores.
+    pub fn ratio(&self) -> f64 {
+        self.ratio_on(RatioBasis::Aggregate)
+    }
+
+    /// Average-ness of the list on `basis`.
+    pub fn ratio_on(&self, basis: RatioBasis) -> f64 {
+        basis.ratio(&self.user_score, &self.global_avg_score)
+    }
+
+    /// `ratio_on(basis)` without the titles whose deltas are in the top and bottom
+    /// `percent`%.
+    pub fn trimmed_ratio(&self, percent: f64, basis: RatioBasis) -> f64 {
+        let outliers = stats::outlier_indices(&self.deltas(), percent);
+        let kept = |scores: &[i64]| -> Vec<i64> {
+            (0..scores.len())
+                .filter(|i| !outliers.contains(i))
+                .map(|i| scores[i])
+                .collect()
+        };
+        basis.ratio(&kept(&self.user_score), &kept(&self.global_avg_score))
+    }
+
+    /// `ratio_on(basis)` with each title weighted by how recently it was updated, halving
+    /// every `halflife_days` before `now`. Titles without an update time are left out.
+    pub fn recency_weighted_ratio(&self, halflife_days: f64, now: i64, basis: RatioBasis) -> f64 {
+        let weights = stats::recency_weights(&self.updated_at, now, halflife_days);
+        let pairs: Vec<(f64, f64)> = self
+            .user_score
+            .iter()
+            .zip(self.global_avg_score.iter())
+            .map(|(user_score, avg_score)| (*user_score as f64, *avg_score as f64))
+            .collect();
+        basis.weighted_ratio(&pairs, &weights)
+    }
+
+    /// `mean_delta` without the top and bottom `percent`% of deltas.
+    pub fn trimmed_mean_delta(&self, percent: f64) -> f64 {
+        let deltas = self.deltas();
+        let outliers = stats::outlier_indices(&deltas, percent);
+        let kept: Vec<i64> = deltas
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !outliers.contains(i))
+            .filter_map(|(_, delta)| delta)
+            .collect();
+        mean(&kept)
+    }
+
+    /// Factor to bring user scores onto the 100-point scale of `averageScore`.
+    /// Users on a 10-point or decimal system never score above 10.
+    pub fn user_score_scale(&self) -> i64 {
+        if let Some(scale) = self.score_scale {
+            scale
+        } else if self.user_score.iter().all(|score| *score <= 10) {
+            10
+        } else {
+            1
+        }
+    }
+
+    /// Signed difference between each normalized user score and the global average.
+    /// None for unscored titles and titles without a global average.
+    pub fn deltas(&self) -> Vec<Option<i64>> {
+        let scale = self.user_score_scale();
+        self.user_score
+            .iter()
+            .zip(self.global_avg_score.iter())
+            .map(|(user_score, avg_score)| {
+                if *user_score == 0 || *avg_score == 0 {
+                    None
+                } else {
+                    Some(user_score * scale - avg_score)
+                }
+            })
+            .collect()
+    }
+
+    pub fn mean_delta(&self) -> f64 {
+        let deltas: Vec<i64> = self.deltas().into_iter().flatten().collect();
+        if deltas.is_empty() {
+            0.0
+        } else {
+            deltas.iter().sum::<i64>() as f64 / deltas.len() as f64
+        }
+    }
+
+    /// Up to `n` titles with the highest global score, highest first.
+    pub fn top_global(&self, n: usize) -> Vec<ScoreEntry> {
+        let mut entries = self.entries();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.global_avg_score));
+        entries.truncate(n);
+        entries
+    }
+
+    /// User and global score of each title with both.
+    fn scored_pairs(&self) -> Vec<(f64, f64)> {
+        self.user_score
+            .iter()
+            .zip(self.global_avg_score.iter())
+            .filter(|(user_score, avg_score)| **user_score != 0 && **avg_score != 0)
+            .map(|(user_score, avg_score)| (*user_score as f64, *avg_score as f64))
+            .collect()
+    }
+
+    /// Normalized user score and global score of each title with both, with the global
+    /// scores min-max rescaled from their own range onto the range of the user's: the
+    /// lowest global score becomes the user's lowest score and the highest their highest.
+    /// Compares the user to where the crowd ranks each title rather than to its absolute
+    /// score, for users who only use part of the scale.
+    pub fn range_normalized_pairs(&self) -> Vec<(f64, f64)> {
+        let scale = self.user_score_scale() as f64;
+        let (user_scores, global_scores): (Vec<f64>, Vec<f64>) = self
+            .scored_pairs()
+            .into_iter()
+            .map(|(user_score, avg_score)| (user_score * scale, avg_score))
+            .unzip();
+        let user_min = user_scores.iter().copied().fold(f64::INFINITY, f64::min);
+        let user_max = user_scores
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let global_scores = stats::min_max_rescale(&global_scores, (user_min, user_max));
+        user_scores.into_iter().zip(global_scores).collect()
+    }
+
+    /// `ratio_on(basis)` against the range-normalized global scores of
+    /// `range_normalized_pairs`.
+    pub fn range_normalized_ratio(&self, basis: RatioBasis) -> f64 {
+        let pairs = self.range_normalized_pairs();
+        basis.weighted_ratio(&pairs, &vec![1.0; pairs.len()])
+    }
+
+    /// `mean_delta` against the range-normalized global scores of `range_normalized_pairs`.
+    pub fn range_normalized_mean_delta(&self) -> f64 {
+        let pairs = self.range_normalized_pairs();
+        if pairs.is_empty() {
+            return 0.0;
+        }
+        pairs
+            .iter()
+            .map(|(user_score, avg_score)| user_score - avg_score)
+            .sum::<f64>()
+            / pairs.len() as f64
+    }
+
+    /// Pearson correlation of user and global scores over the titles with both.
+    /// None if fewer than two such titles or either score never varies.
+    pub fn correlation(&self) -> Option<f64> {
+        pearson(&self.scored_pairs())
+    }
+
+    /// Spearman rank correlation of user and global scores over the titles with both, so
+    /// only their order matters and not the scale. None like `correlation`.
+    pub fn spearman_correlation(&self) -> Option<f64> {
+        stats::spearman(&self.scored_pairs())
+    }
+
+    /// Reorder the entries by `anilist_id`. Columns left empty stay empty.
+    pub fn sort_by_id(&mut self) {
+        let mut order: Vec<usize> = (0..self.anilist_id.len()).collect();
+        order.sort_by_key(|i| self.anilist_id[*i]);
+        self.select(&order);
+    }
+
+    /// Drop titles that are not yet released, whose global scores are missing or based
+    /// on few early ratings.
+    pub fn exclude_unreleased(&mut self) {
+        let indices: Vec<usize> = (0..self.anilist_id.len())
+            .filter(|i| self.airing_status.get(*i).map(String::as_str) != Some(NOT_YET_RELEASED))
+            .collect();
+        self.select(&indices);
+    }
+
+    /// Keep only the entries at `order`, in that order. Columns left empty stay empty.
+    fn select(&mut self, order: &[usize]) {
+        fn pick<T: Clone>(values: &[T], order: &[usize]) -> Vec<T> {
+            if values.is_empty() {
+                return vec![];
+            }
+            order.iter().map(|i| values[*i].clone()).collect()
+        }
+        self.anilist_id = pick(&self.anilist_id, order);
+        self.title = pick(&self.title, order);
+        self.user_score = pick(&self.user_score, order);
+        self.global_avg_score = pick(&self.global_avg_score, order);
+        self.updated_at = pick(&self.updated_at, order);
+        self.status = pick(&self.status, order);
+        self.notes = pick(&self.notes, order);
+        self.release_year = pick(&self.release_year, order);
+        self.genres = pick(&self.genres, order);
+        self.tags = pick(&self.tags, order);
+        self.repeat = pick(&self.repeat, order);
+        self.progress = pick(&self.progress, order);
+        self.length = pick(&self.length, order);
+        self.airing_status = pick(&self.airing_status, order);
+    }
+
+    /// Share of each title watched or read, as a percentage of its length. None when the
+    /// length is unknown, e.g. for ongoing series, or progress was not fetched.
+    pub fn completion_pct(&self) -> Vec<Option<f64>> {
+        (0..self.anilist_id.len())
+            .map(|i| {
+                let progress = *self.progress.get(i)?;
+                let length = self
+                    .length
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .filter(|len| *len > 0)?;
+                Some((progress as f64 / length as f64 * 1000.0).round() / 10.0)
+            })
+            .collect()
+    }
+
+    /// Up to `n` titles whose normalized user score differs most from the global average,
+    /// with that difference.
+    pub fn most_divergent(&self, n: usize) -> Vec<(ScoreEntry, i64)> {
+        let mut divergent: Vec<(ScoreEntry, i64)> = self
+            .entries()
+            .into_iter()
+            .zip(self.deltas())
+            .filter_map(|(entry, delta)| delta.map(|delta| (entry, delta)))
+            .collect();
+        divergent.sort_by_key(|(_, delta)| std::cmp::Reverse(delta.abs()));
+        divergent.truncate(n);
+        divergent
+    }
+
+    /// Count titles rated above, below, or equal to the global average.
+    /// Unscored titles and titles without a global average are skipped.
+    pub fn compare_to_average(&self) -> ScoreComparison {
+        let scale = self.user_score_scale();
+        let mut comparison = ScoreComparison::default();
+
+        for (user_score, avg_score) in self.user_score.iter().zip(self.global_avg_score.iter()) {
+            if *user_score == 0 || *avg_score == 0 {
+                continue;
+            }
+            match (user_score * scale).cmp(avg_score) {
+                std::cmp::Ordering::Greater => comparison.above += 1,
+                std::cmp::Ordering::Less => comparison.below += 1,
+                std::cmp::Ordering::Equal => comparison.equal += 1,
+            }
+        }
+        comparison
+    }
+
+    /// Average-ness ratio on `basis` of the titles last updated in each time bucket,
+    /// oldest first. Entries without an update time are skipped.
+    pub fn score_trend(&self, bucket: TrendBucket, basis: RatioBasis) -> Vec<(String, usize, f64)> {
+        self.ratio_by(basis, |i| match self.updated_at[i] {
+            0 => None,
+            updated_at => bucket.label(updated_at),
+        })
+    }
+
+    /// Average-ness ratio on `basis` of the titles released in each year, oldest first.
+    /// Titles without a known release year are skipped.
+    pub fn score_by_year(&self, basis: RatioBasis) -> Vec<(String, usize, f64)> {
+        self.ratio_by(basis, |i| match self.release_year[i] {
+            0 => None,
+            year => Some(year.to_string()),
+        })
+    }
+
+    /// Average-ness ratio on `basis` of the titles grouped by the label `key` gives each
+    /// index, in label order. Titles `key` gives no label are skipped.
+    fn ratio_by(
+        &self,
+        basis: RatioBasis,
+        key: impl Fn(usize) -> Option<String>,
+    ) -> Vec<(String, usize, f64)> {
+        let mut buckets: BTreeMap<String, (Vec<i64>, Vec<i64>)> = BTreeMap::new();
+
+        for i in 0..self.anilist_id.len() {
+            if let Some(label) = key(i) {
+                let (user_scores, avg_scores) = buckets.entry(label).or_default();
+                user_scores.push(self.user_score[i]);
+                avg_scores.push(self.global_avg_score[i]);
+            }
+        }
+
+        buckets
+            .into_iter()
+            .map(|(label, (user_scores, avg_scores))| {
+                let ratio = basis.ratio(&user_scores, &avg_scores);
+                (label, user_scores.len(), ratio)
+            })
+            .collect()
+    }
+
+    pub fn to_csv(&self, fname: &str) -> Result<(), String> {
+        let writer = CsvScoreWriter {
+            delimiter: b',',
+            options: WriterOptions::default(),
+        };
+        self.to_file(&writer, fname)
+    }
+
+    /// Write to stdout as a single block under the stdout lock, so lists written from
+    /// several threads never interleave.
+    pub fn to_stdout(&self, writer: &dyn ScoreWriter) -> io::Result<()> {
+        let mut block: Vec<u8> = vec![];
+        writer.write(self, &mut block)?;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&block)?;
+        stdout.flush()
+    }
+
+    /// Add to the end of `fname`, created if missing. Only new or empty files get a header.
+    pub fn append_to_file(&self, writer: &dyn ScoreWriter, fname: &str) -> io::Result<()> {
+        let mut output_fh = OpenOptions::new().create(true).append(true).open(fname)?;
+        if output_fh.metadata()?.len() == 0 {
+            writer.write(self, &mut output_fh)
+        } else {
+            writer.write_rows(self, &mut output_fh)
+        }
+    }
+
+    pub fn to_file(&self, writer: &dyn ScoreWriter, fname: &str) -> Result<(), String> {
+        let mut output_fh = File::create(fname)
+            .map_err(|err| format!("Unable to create file at {fname}: {err}"))?;
+        writer
+            .write(self, &mut output_fh)
+            .map_err(|err| format!("Unable to save file to {fname}: {err}"))
+    }
+}
+
+/// Write `df` with a header to the csv file `fname`.
+fn write_csv(fname: &str, df: &mut DataFrame) -> Result<(), String> {
+    let output_fh =
+        File::create(fname).map_err(|err| format!("Unable to create file at {fname}: {err}"))?;
+    CsvWriter::new(output_fh)
+        .has_header(true)
+        .finish(df)
+        .map_err(|err| format!("Unable to save file to {fname}: {err}"))
+}
+
+/// `fname`, or with the first free numeric suffix (`name-1.csv`, `name-2.csv`, ...) if
+/// `no_clobber` is set and it already exists.
+fn output_path(fname: &str, no_clobber: bool) -> String {
+    let path = Path::new(fname);
+    if !no_clobber || !path.exists() {
+        return fname.to_string();
+    }
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(fname);
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| format!(".{extension}"))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| path.with_file_name(format!("{stem}-{i}{extension}")))
+        .find(|candidate| !candidate.exists())
+        .map(|candidate| candidate.to_string_lossy().to_string())
+        .unwrap_or_else(|| fname.to_string())
+}
+
+/// Directory output files are written to: `dir`, or its `anime`/`manga` subdirectory
+/// when grouping by media.
+fn output_dir(dir: &str, media: &str, group_by_media: bool) -> PathBuf {
+    let dir = Path::new(dir);
+    if group_by_media {
+        dir.join(media.to_lowercase())
+    } else {
+        dir.to_path_buf()
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Whether Ctrl-C was pressed since `install_interrupt_handler` was called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Exit with the interrupted status if Ctrl-C was pressed, so no later phase fetches or
+/// writes anything once the lists fetched so far are written.
+fn exit_if_interrupted(anilist_scores: &[AnilistScores]) {
+    if interrupted() {
+        let titles_written: usize = anilist_scores
+            .iter()
+            .map(|score| score.anilist_id.len())
+            .sum();
+        eprintln!("Wrote {titles_written} titles before interruption.");
+        process::exit(130);
+    }
+}
+
+/// On the first Ctrl-C, stop fetching further lists so the lists fetched so far are still
+/// written. A second Ctrl-C exits immediately.
+fn install_interrupt_handler() {
+    thread::spawn(|| {
+        let runtime = tokio::runtime::Runtime::new().expect("Unable to start signal handler.");
+        runtime.block_on(async {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+                eprintln!("Interrupted. Writing titles fetched so far...");
+            }
+            if tokio::signal::ctrl_c().await.is_ok() {
+                process::exit(130);
+            }
+        });
+    });
+}
+
+#[tokio::main]
+async fn run_query(
+    client: &AnilistClient,
+    json_query: Value,
+) -> Result<serde_json::Value, AnilistError> {
+    client.query(&json_query).await
+}
+
+#[tokio::main]
+async fn run_check(client: &AnilistClient) -> Result<HealthCheck, AnilistError> {
+    client.check().await
+}
+
+/// Print each scored entry as a json line as soon as its chunk of global scores arrives.
+/// Failed chunks are reported and skipped. Returns whether any failed.
+#[tokio::main]
+async fn print_score_stream(
+    username: &str,
+    media: &str,
+    options: &QueryOptions,
+    error_format: ErrorFormat,
+) -> bool {
+    let client = options.client();
+    let mut entries = pin!(stream::stream_scores(&client, username, media, options));
+    let mut failed = false;
+    while let Some(entry) = entries.next().await {
+        match entry {
+            Ok(entry) => println!(
+                "{}",
+                json!({
+                    "anilist_id": entry.anilist_id,
+                    "title": entry.title,
+                    "user_score": entry.user_score,
+                    "global_avg_score": entry.global_avg_score,
+                    "updated_at": entry.updated_at,
+                    "status": entry.status,
+                })
+            ),
+            // Later chunks would fail the same way, so stop with what was printed.
+            Err(err @ AnilistError::RuntimeExceeded { .. }) => {
+                io::stdout().flush().ok();
+                error_format.report(&err);
+                return true;
+            }
+            Err(err) => {
+                error_format.report(&err);
+                failed = true;
+            }
+        }
+    }
+    failed
+}
+
+/// Scores of a list's entries as Anilist returned them, before any conversion.
+fn raw_scores(list: &MediaList) -> Vec<f64> {
+    list.entries
+        .iter()
+        .filter_map(|entry| entry.score)
+        .collect()
+}
+
+/// Entries of a list. With a `score_format`, scores are converted from it to the 100-point
+/// scale. Without one, a list with any decimal score (e.g. 7.5) is read as
+/// POINT_10_DECIMAL and converted the same way, since only that format has them; other
+/// lists keep their scores as is.
+pub fn parse_entry_values(list: &MediaList, score_format: Option<ScoreFormat>) -> ListEntries {
+    let score_format = score_format.or_else(|| {
+        list.entries
+            .iter()
+            .filter_map(|entry| entry.score)
+            .any(|score| score.fract() != 0.0)
+            .then_some(ScoreFormat::Point10Decimal)
+    });
+    let mut entries = ListEntries {
+        score_format,
+        ..Default::default()
+    };
+
+    for entry in list.entries.iter() {
+        let score = match (score_format, entry.score) {
+            (Some(format), Some(score)) => Some(format.to_point_100(score)),
+            (None, Some(score)) => Some(score as i64),
+            _ => None,
+        };
+        if let (Some(id), Some(score)) = (entry.media_id, score) {
+            entries.anilist_id.push(id);
+            entries.user_score.push(score);
+            entries.updated_at.push(entry.updated_at.unwrap_or(0));
+            entries.private.push(entry.private.unwrap_or(false));
+            entries
+                .status
+                .push(entry.status.clone().unwrap_or_default());
+            entries.notes.push(entry.notes.clone().unwrap_or_default());
+            entries.repeat.push(entry.repeat.unwrap_or(0));
+            entries.progress.push(entry.progress.unwrap_or(0));
+            entries.length.push(
+                entry
+                    .media
+                    .as_ref()
+                    .and_then(|media| media.episodes.or(media.chapters)),
+            );
+        }
+    }
+
+    entries
+}
+
+/// Largest number of ids per average score query that stays within Anilist's complexity
+/// limit, with or without `tags`.
+pub fn max_chunk_size(tags: bool) -> usize {
+    (1..)
+        .take_while(|id_count| {
+            estimate_avg_score_complexity(*id_count, tags) <= MAX_QUERY_COMPLEXITY
+        })
+        .last()
+        .unwrap_or(1)
+}
+
+/// Smallest chunk a chunk rejected as too complex is split into.
+const MIN_SPLIT_CHUNK_SIZE: usize = 5;
+
+/// Cap a requested chunk size to `max_chunk_size`, warning when it had to be reduced.
+pub fn checked_chunk_size(chunk_size: usize, tags: bool) -> usize {
+    let max_size = max_chunk_size(tags);
+    if chunk_size > max_size {
+        eprintln!(
+            "Chunk size {chunk_size} has an estimated complexity of {}, over Anilist's limit of {MAX_QUERY_COMPLEXITY}. Reducing it to {max_size}.",
+            estimate_avg_score_complexity(chunk_size, tags)
+        );
+        max_size
+    } else {
+        chunk_size.max(1)
+    }
+}
+
+/// Global score and details of each of `media_ids`, keyed by media id. Details include
+/// tags if `tags` is set.
+///
+/// A chunk that fails after its retries only loses its own ids: they are left out of the
+/// map, with a warning listing them. Err only if every chunk failed.
+pub fn run_query_avg_scores(
+    client: &AnilistClient,
+    media: &str,
+    media_ids: &[i64],
+    basis: ScoreBasis,
+    chunk_size: usize,
+    tags: bool,
+) -> Result<HashMap<i64, MediaDetails>, AnilistError> {
+    fetch_chunks(media_ids, checked_chunk_size(chunk_size, tags), |chunk| {
+        run_query_avg_scores_chunk(client, media, chunk, basis, tags)
+    })
+}
+
+/// Merge the results of `fetch` over each chunk of `media_ids`, skipping failed chunks.
+/// A chunk Anilist rejects as too complex is retried as two halves, down to chunks of
+/// `MIN_SPLIT_CHUNK_SIZE`.
+fn fetch_chunks(
+    media_ids: &[i64],
+    chunk_size: usize,
+    mut fetch: impl FnMut(&[i64]) -> Result<HashMap<i64, MediaDetails>, AnilistError>,
+) -> Result<HashMap<i64, MediaDetails>, AnilistError> {
+    let mut avg_scores: HashMap<i64, MediaDetails> = HashMap::new();
+    let mut failed: Vec<i64> = vec![];
+    let mut last_err = None;
+    let mut pending: VecDeque<&[i64]> = media_ids.chunks(chunk_size).collect();
+    while let Some(chunk) = pending.pop_front() {
+        match fetch(chunk) {
+            Ok(chunk_scores) => avg_scores.extend(chunk_scores),
+            Err(AnilistError::QueryTooComplex(_)) if chunk.len() > MIN_SPLIT_CHUNK_SIZE => {
+                let (first, second) = chunk.split_at(chunk.len() / 2);
+                eprintln!(
+                    "Chunk of {} titles was too complex for Anilist. Retrying as chunks of {} and {}.",
+                    chunk.len(),
+                    first.len(),
+                    second.len()
+                );
+                pending.push_front(second);
+                pending.push_front(first);
+            }
+            // Every other chunk would fail alike.
+            Err(
+                err @ (AnilistError::RuntimeExceeded { .. }
+                | AnilistError::Unauthorized(_)
+                | AnilistError::RateLimited),
+            ) => return Err(err),
+            Err(err) => {
+                failed.extend_from_slice(chunk);
+                last_err = Some(err);
+            }
+        }
+    }
+
+    match last_err {
+        Some(err) if failed.len() == media_ids.len() => Err(err),
+        Some(err) => {
+            let ids: Vec<String> = failed.iter().map(|id| id.to_string()).collect();
+            eprintln!(
+                "Warning: global scores of {} titles could not be fetched ({err}): {}",
+                failed.len(),
+                ids.join(", ")
+            );
+            Ok(avg_scores)
+        }
+        None => Ok(avg_scores),
+    }
+}
+
+/// Map the aliased `Media` fields of an average score response back to the ids they were
+/// queried for.
+fn parse_avg_scores(
+    avg_score_value: &Value,
+    media_ids: &[i64],
+    basis: ScoreBasis,
+) -> Result<HashMap<i64, MediaDetails>, AnilistError> {
+    let aliased: HashMap<String, Option<Media>> = responses::parse(avg_score_value, "data")?;
+    Ok(aliased
+        .into_iter()
+        .filter_map(|(k, media)| {
+            let index: usize = k.replace("query_", "").parse().ok()?;
+            let id = media_ids.get(index)?;
+            let media = media?;
+            let global_score = basis.score_of(&media);
+            Some((*id, MediaDetails::from_media(media, global_score)))
+        })
+        .collect())
+}
+
+/// Request body of an average score query for `media_ids`, with their tags if `tags`.
+pub fn avg_score_query(media: &str, media_ids: &[i64], basis: ScoreBasis, tags: bool) -> Value {
+    let mut variables = avg_score_variables(media, media_ids);
+    variables["tags"] = json!(tags);
+    json!(
+        {
+            "query": build_avg_score_query(media_ids.len(), basis.field()),
+            "variables": variables
+        }
+    )
+}
+
+fn run_query_avg_scores_chunk(
+    client: &AnilistClient,
+    media: &str,
+    media_ids: &[i64],
+    basis: ScoreBasis,
+    tags: bool,
+) -> Result<HashMap<i64, MediaDetails>, AnilistError> {
+    let res = run_query(client, avg_score_query(media, media_ids, basis, tags))?;
+    let avg_score_value = AnilistError::require_path(&res, &["data"])?;
+    parse_avg_scores(avg_score_value, media_ids, basis)
+}
+
+/// How the user's scores are summarized against the global scores.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// Sum of user scores over sum of global scores.
+    #[default]
+    Ratio,
+    /// Mean of `user_score - global_avg_score`, after normalization.
+    Delta,
+}
+
+impl Metric {
+    pub fn from_flag(value: &str) -> Option<Metric> {
+        match value.to_lowercase().as_str() {
+            "ratio" => Some(Metric::Ratio),
+            "delta" => Some(Metric::Delta),
+            _ => None,
+        }
+    }
+}
+
+/// What to do with user scores outside 0 to 100 once on the 100-point scale, e.g. from a
+/// manual edit. A warning is printed either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidScore {
+    /// Bring them to the nearest end of the scale.
+    Clamp,
+    /// Drop their entries.
+    Skip,
+    #[default]
+    Keep,
+}
+
+impl InvalidScore {
+    pub fn from_flag(value: &str) -> Option<InvalidScore> {
+        match value.to_lowercase().as_str() {
+            "clamp" => Some(InvalidScore::Clamp),
+            "skip" => Some(InvalidScore::Skip),
+            "keep" => Some(InvalidScore::Keep),
+            _ => None,
+        }
+    }
+}
+
+/// Anilist's `ScoreFormat`, the scale a user scores on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreFormat {
+    Point100,
+    Point10,
+    /// Out of 10 with one decimal, e.g. 4.9.
+    Point10Decimal,
+    Point5,
+    /// Smileys, stored as 1 to 3.
+    Point3,
+}
+
+impl ScoreFormat {
+    pub fn from_flag(value: &str) -> Option<ScoreFormat> {
+        match value.to_uppercase().as_str() {
+            "POINT_100" => Some(ScoreFormat::Point100),
+            "POINT_10" => Some(ScoreFormat::Point10),
+            "POINT_10_DECIMAL" => Some(ScoreFormat::Point10Decimal),
+            "POINT_5" => Some(ScoreFormat::Point5),
+            "POINT_3" => Some(ScoreFormat::Point3),
+            _ => None,
+        }
+    }
+
+    /// Highest score of this format.
+    fn max(&self) -> f64 {
+        match self {
+            ScoreFormat::Point100 => 100.0,
+            ScoreFormat::Point10 | ScoreFormat::Point10Decimal => 10.0,
+            ScoreFormat::Point5 => 5.0,
+            ScoreFormat::Point3 => 3.0,
+        }
+    }
+
+    /// `score` in this format on the 100-point scale of `averageScore`.
+    pub fn to_point_100(&self, score: f64) -> i64 {
+        (score * 100.0 / self.max()).round() as i64
+    }
+
+    /// Warning if the raw `scores` of `list_type` don't look like this format, either
+    /// because some are above its maximum or because `user_score_scale` would have taken
+    /// 100-point scores for a 10-point scale. Unscored entries are ignored.
+    pub fn conflict_warning(&self, list_type: &str, scores: &[f64]) -> Option<String> {
+        let scored: Vec<f64> = scores
+            .iter()
+            .copied()
+            .filter(|score| *score > 0.0)
+            .collect();
+        let highest = scored.iter().copied().fold(0.0, f64::max);
+        if highest > self.max() {
+            Some(format!(
+                "'{list_type}' has scores up to {highest}, above the maximum of {} of the declared score format. Using the declared format anyway.",
+                self.max()
+            ))
+        } else if *self == ScoreFormat::Point100 && !scored.is_empty() && highest <= 10.0 {
+            Some(format!(
+                "'{list_type}' has no score above 10, which looks like a 10-point scale, but POINT_100 was declared. Using the declared format anyway."
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// How the scores of many titles are summarized into one average-ness ratio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RatioBasis {
+    /// Summed user scores over summed global scores, see `stats::aggregate_ratio`.
+    #[default]
+    Aggregate,
+    /// Mean of the per-title ratios, see `stats::mean_of_ratios`.
+    Mean,
+}
+
+impl RatioBasis {
+    pub fn from_flag(value: &str) -> Option<RatioBasis> {
+        match value.to_lowercase().as_str() {
+            "aggregate" => Some(RatioBasis::Aggregate),
+            "mean" => Some(RatioBasis::Mean),
+            _ => None,
+        }
+    }
+
+    /// Ratio of the `user` scores to the `global` scores of the same titles.
+    pub fn ratio(&self, user: &[i64], global: &[i64]) -> f64 {
+        match self {
+            RatioBasis::Aggregate => stats::aggregate_ratio(user, global),
+            RatioBasis::Mean => stats::mean_of_ratios(user, global),
+        }
+    }
+
+    /// `ratio` of `(user, global)` score pairs, each counting for its weight in `weights`.
+    pub fn weighted_ratio(&self, pairs: &[(f64, f64)], weights: &[f64]) -> f64 {
+        let weighted = pairs.iter().zip(weights.iter());
+        match self {
+            RatioBasis::Aggregate => {
+                let (user_sum, global_sum) = weighted
+                    .fold((0.0, 0.0), |(user, global), ((u, g), w)| {
+                        (user + u * w, global + g * w)
+                    });
+                user_sum / global_sum
+            }
+            RatioBasis::Mean => {
+                let (ratio_sum, weight_sum) = weighted
+                    .filter(|((_, global), _)| *global != 0.0)
+                    .fold((0.0, 0.0), |(ratios, total), ((u, g), w)| {
+                        (ratios + u / g * w, total + w)
+                    });
+                ratio_sum / weight_sum
+            }
+        }
+    }
+}
+
+/// Global score the user's scores are compared against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScoreBasis {
+    /// Anilist's `averageScore`, weighted toward titles with more votes.
+    #[default]
+    Average,
+    /// Anilist's `meanScore`, the plain mean of all user scores.
+    Mean,
+}
+
+impl ScoreBasis {
+    pub fn from_flag(value: &str) -> Option<ScoreBasis> {
+        match value.to_lowercase().as_str() {
+            "average" => Some(ScoreBasis::Average),
+            "mean" => Some(ScoreBasis::Mean),
+            _ => None,
+        }
+    }
+
+    /// Global score of `media` on this basis.
+    pub fn score_of(&self, media: &Media) -> Option<i64> {
+        match self {
+            ScoreBasis::Average => media.average_score,
+            ScoreBasis::Mean => media.mean_score,
+        }
+    }
+
+    /// Media field queried for this basis.
+    pub fn field(&self) -> &'static str {
+        match self {
+            ScoreBasis::Average => "averageScore",
+            ScoreBasis::Mean => "meanScore",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Times to re-issue the list query if it unexpectedly returns no lists.
+    pub retry_on_empty: u32,
+    /// Keep only the first N entries of each list, in the order Anilist returns them.
+    /// This is not a random sample.
+    pub limit: Option<usize>,
+    /// Randomly sample N entries of each list.
+    pub sample: Option<usize>,
+    /// Seed for `sample` so runs are reproducible. Random if unset.
+    pub seed: Option<u64>,
+    pub basis: ScoreBasis,
+    /// Drop entries hidden from other users.
+    pub exclude_private: bool,
+    /// Print how long each fetch phase took.
+    pub timing: bool,
+    pub retry: RetryPolicy,
+    /// Ids per average score query. Defaults to the most Anilist's complexity limit allows.
+    pub chunk_size: Option<usize>,
+    /// Skip fetching global scores. `global_avg_score` is left as zeros.
+    pub no_global: bool,
+    /// Which name fills the `title` column.
+    pub title_lang: TitleLanguage,
+    /// Previous run of each list, keyed by list name. Global scores of titles not updated
+    /// since are reused instead of fetched.
+    pub previous_runs: HashMap<String, HashMap<i64, PreviousEntry>>,
+    /// User-Agent sent with each query. `DEFAULT_USER_AGENT` if unset.
+    pub user_agent: Option<String>,
+    /// GraphQL endpoints to query, Anilist if empty.
+    pub endpoints: Vec<String>,
+    /// Idle connections kept per host, reqwest's default if unset.
+    pub pool_size: Option<usize>,
+    /// Also fetch the planning list and the global scores of its titles.
+    pub planning_report: bool,
+    /// Also fetch the lists of `STATUS_CHANGE_LISTS` for the status changes report.
+    pub status_changes: bool,
+    /// Keep only entries with one of these `MediaListStatus` values, from any list.
+    /// Lists are picked by name when empty.
+    pub statuses: Vec<String>,
+    /// Also fetch the tags of each title.
+    pub tags: bool,
+    /// Request the notes of each entry.
+    pub include_notes: bool,
+    /// Drop titles that are not yet released.
+    pub exclude_unreleased: bool,
+    /// What to do with user scores outside 0 to 100.
+    pub on_invalid_score: InvalidScore,
+    /// Take global scores only from `previous_runs`, failing with the ids it lacks instead
+    /// of querying them.
+    pub cache_only: bool,
+    /// Request the progress of each entry and the length of its title.
+    pub include_progress: bool,
+    /// Refuse to fetch global scores when the lists hold more entries than this.
+    pub max_entries: Option<usize>,
+    /// Scale the user scores on, instead of detecting it from the scores.
+    pub score_format: Option<ScoreFormat>,
+    /// Warn when the entries fetched are fewer than this share of the entries the user's
+    /// statistics count for the same statuses. 0 disables the check.
+    pub warn_threshold: f64,
+    /// Custom lists to fetch besides the default lists, by name. Lists the query can't see,
+    /// e.g. private ones when no token is set, are skipped.
+    pub custom_lists: Vec<String>,
+    /// Lists to drop after picking them, by name as parsed by `parse_list_names`.
+    pub exclude_lists: Vec<String>,
+    /// Print which lists the list query returned.
+    pub verbose: bool,
+    /// Abort the run's queries at this point, for `--max-runtime-secs`.
+    pub deadline: Option<Deadline>,
+    /// Answers queries in place of Anilist, e.g. canned responses in tests.
+    pub transport: Option<Arc<dyn Transport>>,
+    /// HTTP requests sent by clients built from these options, shared by clones.
+    pub requests: Arc<AtomicUsize>,
+}
+
+impl QueryOptions {
+    /// Client applying the User-Agent and retry policy of these options.
+    pub fn client(&self) -> AnilistClient {
+        AnilistClient::new(self.user_agent.as_deref(), self.retry.clone())
+            .with_endpoints(&self.endpoints)
+            .with_pool_size(self.pool_size)
+            .with_deadline(self.deadline)
+            .with_transport(self.transport.clone())
+            .with_request_count(self.requests.clone())
+    }
+
+    /// HTTP requests sent by clients built from these options, including retries.
+    pub fn requests_made(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Statuses of the entries these options fetch, without those of excluded lists.
+    pub fn expected_statuses(&self) -> Vec<String> {
+        let mut statuses = if !self.statuses.is_empty() {
+            self.statuses.clone()
+        } else {
+            let mut statuses = vec!["CURRENT".to_string(), "COMPLETED".to_string()];
+            if self.planning_report {
+                statuses.push("PLANNING".to_string());
+            }
+            if self.status_changes {
+                statuses.extend(["DROPPED".to_string(), "REPEATING".to_string()]);
+            }
+            statuses
+        };
+        statuses.retain(|status| {
+            !self
+                .exclude_lists
+                .iter()
+                .any(|name| list_status(name) == Some(status.as_str()))
+        });
+        statuses
+    }
+}
+
+/// Number of the user's `media` entries with each status, from their statistics.
+pub fn get_status_counts(
+    client: &AnilistClient,
+    username: &str,
+    media: &str,
+) -> Result<HashMap<String, usize>, AnilistError> {
+    let query = json!(
+        {
+            "query": build_status_counts_query(media),
+            "variables": {"username": username}
+        }
+    );
+    let res = run_query(client, query)?;
+    let statuses = res
+        .pointer(&format!(
+            "/data/User/statistics/{}/statuses",
+            media.to_lowercase()
+        ))
+        .filter(|value| !value.is_null())
+        .ok_or_else(|| AnilistError::from_response(&res))?;
+    let statuses: Vec<StatusCount> = responses::parse(statuses, "data.User.statistics.statuses")?;
+    Ok(statuses
+        .into_iter()
+        .map(|status| (status.status, status.count))
+        .collect())
+}
+
+/// Overall anime and manga statistics of a user, e.g. how many entries they have and
+/// their mean score.
+pub fn get_user_statistics(
+    client: &AnilistClient,
+    username: &str,
+) -> Result<UserStats, AnilistError> {
+    let query = json!(
+        {
+            "query": QUERY_USER_STATISTICS,
+            "variables": {"username": username}
+        }
+    );
+    let res = run_query(client, query)?;
+    let statistics = res
+        .pointer("/data/User/statistics")
+        .filter(|value| !value.is_null())
+        .ok_or_else(|| AnilistError::from_response(&res))?;
+    responses::parse(statistics, "data.User.statistics")
+}
+
+/// Line describing a user's `media` statistics, e.g. ahead of the report.
+pub fn user_stats_line(username: &str, media: &str, stats: &UserStats) -> String {
+    let media_stats = stats.of(media);
+    let consumed = if media.eq_ignore_ascii_case("MANGA") {
+        format!("{} chapters read", media_stats.chapters_read)
+    } else {
+        format!("{} minutes watched", media_stats.minutes_watched)
+    };
+    format!(
+        "{username} has {} {} entries with a mean score of {:.1} and {consumed}.",
+        media_stats.count,
+        media.to_lowercase(),
+        media_stats.mean_score
+    )
+}
+
+/// Lists of a user's `media`, with only the ids of their entries.
+pub fn get_media_lists(
+    client: &AnilistClient,
+    username: &str,
+    media: &str,
+) -> Result<Vec<MediaList>, AnilistError> {
+    let query = json!(
+        {
+            "query": QUERY_USER_LISTS,
+            "variables": {"username": username, "media": media}
+        }
+    );
+    media_lists_of(&run_query(client, query)?)
+}
+
+/// Warning when only `fetched` of the `expected` entries came back, below `threshold`.
+pub fn yield_warning(fetched: usize, expected: usize, threshold: f64) -> Option<String> {
+    if expected == 0 || fetched as f64 / expected as f64 >= threshold {
+        return None;
+    }
+    Some(format!(
+        "Warning: only fetched {fetched} of the {expected} entries your statistics count ({:.0}%). Some lists may be missing or partially fetched.",
+        100.0 * fetched as f64 / expected as f64
+    ))
+}
+
+/// Ids and global scores of a user's favourite `media`, across all pages.
+pub fn get_favourite_scores(
+    username: &str,
+    media: &str,
+    options: &QueryOptions,
+) -> Result<Vec<(i64, i64)>, AnilistError> {
+    let client = options.client();
+    let query = build_favourites_query(media, options.basis.field());
+    let media_key = media.to_lowercase();
+    let mut favourites: Vec<(i64, i64)> = vec![];
+
+    for page in 1.. {
+        let favourites_query = json!(
+            {
+                "query": query,
+                "variables": {"username": username, "page": page}
+            }
+        );
+        let res = run_query(&client, favourites_query)?;
+        let favourites_page = res
+            .pointer(&format!("/data/User/favourites/{media_key}"))
+            .filter(|value| !value.is_null())
+            .ok_or_else(|| AnilistError::from_response(&res))?;
+        let favourites_page: FavouritesPage =
+            responses::parse(favourites_page, "data.User.favourites")?;
+
+        for node in favourites_page.nodes {
+            if let Some(id) = node.id {
+                favourites.push((id, options.basis.score_of(&node.media).unwrap_or(0)));
+            }
+        }
+
+        if !favourites_page.page_info.has_next_page {
+            break;
+        }
+    }
+    Ok(favourites)
+}
+
+/// Lists of a `MediaListCollection` query response.
+pub fn media_lists_of(query_res: &Value) -> Result<Vec<MediaList>, AnilistError> {
+    let lists = AnilistError::require_path(query_res, &["data", "MediaListCollection", "lists"])?;
+    responses::parse(lists, "data.MediaListCollection.lists")
+}
+
+fn query_media_lists(
+    client: &AnilistClient,
+    user_media_query: &Value,
+    options: &QueryOptions,
+) -> Result<Vec<MediaList>, AnilistError> {
+    let retry_on_empty = options.retry_on_empty;
+    let mut retries = 0;
+    loop {
+        let query_res = run_query(client, user_media_query.clone())?;
+        let media_lists = media_lists_of(&query_res)?;
+
+        if !media_lists.is_empty() || retries >= retry_on_empty {
+            return Ok(media_lists);
+        }
+        retries += 1;
+        eprintln!("No media lists returned. Retrying ({retries}/{retry_on_empty})...");
+    }
+}
+
+/// Suffix repeated list names with their occurrence (`Completed`, `Completed-2`, ...) so
+/// lists sharing a name don't overwrite each other's output files.
+pub fn disambiguate_list_names(anilist_scores: &mut [AnilistScores]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for scores in anilist_scores.iter_mut() {
+        let count = seen.entry(scores.list_type.clone()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            scores.list_type = format!("{}-{count}", scores.list_type);
+        }
+    }
+}
+
+/// Entries on the lists of a `MediaListCollection`. Custom lists only repeat entries of the
+/// status lists, so they are not counted.
+pub fn total_entries(media_lists: &[MediaList]) -> usize {
+    media_lists
+        .iter()
+        .filter(|list| !list.is_custom_list)
+        .map(|list| list.entries.len())
+        .sum()
+}
+
+/// Fill in the global scores and release years of `lists` from the previous run of each,
+/// failing with the ids of every title it lacks.
+fn fill_from_cache(
+    lists: &mut [AnilistScores],
+    previous_runs: &HashMap<String, HashMap<i64, PreviousEntry>>,
+) -> Result<(), AnilistError> {
+    let mut uncached = vec![];
+    for list in lists.iter_mut() {
+        let previous = previous_runs.get(&list.list_type);
+        uncached.extend(uncached_ids(&list.anilist_id, previous));
+        let cached: Vec<Option<&PreviousEntry>> = list
+            .anilist_id
+            .iter()
+            .map(|id| previous.and_then(|previous| previous.get(id)))
+            .collect();
+        list.global_avg_score = cached
+            .iter()
+            .map(|entry| entry.map_or(0, |entry| entry.global_avg_score))
+            .collect();
+        list.release_year = cached
+            .iter()
+            .map(|entry| entry.map_or(0, |entry| entry.release_year))
+            .collect();
+    }
+    if uncached.is_empty() {
+        Ok(())
+    } else {
+        uncached.sort_unstable();
+        Err(AnilistError::NotCached(uncached))
+    }
+}
+
+/// Edits needed to turn `a` into `b`, counting insertions, deletions and substitutions.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Name of `candidates` closest to `name`, ignoring case, for "did you mean" hints. None
+/// if even the closest differs in more than half its characters.
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    candidates
+        .map(|candidate| (candidate, levenshtein(&name, &candidate.to_lowercase())))
+        .filter(|(candidate, distance)| {
+            distance * 2 <= name.chars().count().max(candidate.chars().count())
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(candidate, _)| candidate)
+}
+
+fn did_you_mean(closest: Option<&str>) -> String {
+    closest
+        .map(|closest| format!(" Did you mean '{closest}'?"))
+        .unwrap_or_default()
+}
+
+/// Lines noting the lists `media_lists` holds, if `verbose`, and each of `custom_lists`
+/// and `exclude_lists` missing from them, with the closest list name. Anilist leaves out
+/// lists the requester can't see, so a missing custom list is either misspelled or
+/// private.
+fn visibility_notes(
+    media_lists: &[MediaList],
+    custom_lists: &[String],
+    exclude_lists: &[String],
+    verbose: bool,
+) -> Vec<String> {
+    let mut notes = vec![];
+    if verbose {
+        let names: Vec<String> = media_lists
+            .iter()
+            .map(|list| {
+                let custom = if list.is_custom_list { " (custom)" } else { "" };
+                format!("{}{custom}", list.name)
+            })
+            .collect();
+        notes.push(format!("Visible lists: {}", names.join(", ")));
+    }
+    for name in custom_lists {
+        let visible = media_lists
+            .iter()
+            .any(|list| list.is_custom_list && &list.name == name);
+        if !visible {
+            let closest = closest_name(
+                name,
+                media_lists
+                    .iter()
+                    .filter(|list| list.is_custom_list)
+                    .map(|list| list.name.as_str()),
+            );
+            notes.push(format!(
+                "Custom list '{name}' is private or doesn't exist. Skipping it.{}",
+                did_you_mean(closest)
+            ));
+        }
+    }
+    for name in exclude_lists {
+        let found = media_lists
+            .iter()
+            .any(|list| is_excluded(&list.name, std::slice::from_ref(name)));
+        if !found {
+            let closest = closest_name(name, media_lists.iter().map(|list| list.name.as_str()));
+            notes.push(format!(
+                "Excluded list '{name}' matches none of your lists.{}",
+                did_you_mean(closest)
+            ));
+        }
+    }
+    notes
+}
+
+pub fn get_anilist_scores(
+    username: &str,
+    media: &str,
+    options: &QueryOptions,
+) -> Result<Vec<AnilistScores>, AnilistError> {
+    let client = options.client();
+    // Define query and variables
+    let user_media_query = json!(
+        {
+            "query": QUERY_USER_MEDIA_SCORE,
+            "variables": {
+                "username": username,
+                "media": media,
+                "notes": options.include_notes,
+                "progress": options.include_progress
+            }
+        }
+    );
+
+    let mut anilist_scores: Vec<AnilistScores> = vec![];
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let list_fetch_start = Instant::now();
+    let media_lists = query_media_lists(&client, &user_media_query, options)?;
+    let list_fetch_time = list_fetch_start.elapsed();
+    if let Some(max) = options.max_entries {
+        let count = total_entries(&media_lists);
+        if count > max {
+            return Err(AnilistError::TooManyEntries { count, max });
+        }
+    }
+    for line in visibility_notes(
+        &media_lists,
+        &options.custom_lists,
+        &options.exclude_lists,
+        options.verbose,
+    ) {
+        eprintln!("{line}");
+    }
+    let mut avg_fetch_time = Duration::ZERO;
+    let mut entries_fetched = 0;
+    let mut uncached = vec![];
+
+    for list in media_lists.iter() {
+        if interrupted() {
+            break;
+        }
+        let list_type = list.name.as_str();
+        // Custom lists repeat entries of the status lists, so they are skipped when
+        // filtering by status.
+        let wanted = if options.statuses.is_empty() {
+            DEFAULT_LISTS.contains(&list_type)
+                || (options.planning_report && list_type == PLANNING_LIST)
+                || (options.status_changes && STATUS_CHANGE_LISTS.contains(&list_type))
+                || (list.is_custom_list
+                    && options.custom_lists.iter().any(|name| name == list_type))
+        } else {
+            !list.is_custom_list
+        } && !is_excluded(list_type, &options.exclude_lists);
+        if let (true, Some(format)) = (wanted, options.score_format) {
+            if let Some(warning) = format.conflict_warning(list_type, &raw_scores(list)) {
+                eprintln!("{warning}");
+            }
+        }
+        let entries = if wanted {
+            Ok(parse_entry_values(list, options.score_format))
+        } else {
+            Err(())
+        };
+
+        if let Ok(mut entries) = entries {
+            if let Some(warning) =
+                entries.handle_invalid_scores(options.on_invalid_score, list_type)
+            {
+                eprintln!("{warning}");
+            }
+            if !options.statuses.is_empty() {
+                entries.with_status(&options.statuses);
+                if entries.is_empty() {
+                    continue;
+                }
+            }
+            // Custom lists repeat entries of the status lists, which the statistics
+            // already count.
+            if !list.is_custom_list {
+                entries_fetched += entries.len();
+            }
+            if options.exclude_private {
+                entries.exclude_private();
+            }
+            if let Some(amount) = options.sample {
+                entries.sample(amount, &mut rng);
+            }
+            if let Some(limit) = options.limit {
+                entries.truncate(limit);
+            }
+            let avg_fetch_start = Instant::now();
+            let fetch_avg_scores = |media_ids: &[i64]| {
+                run_query_avg_scores(
+                    &client,
+                    media,
+                    media_ids,
+                    options.basis,
+                    options
+                        .chunk_size
+                        .unwrap_or_else(|| max_chunk_size(options.tags)),
+                    options.tags,
+                )
+            };
+            let avg_scores = if options.no_global {
+                Ok(HashMap::new())
+            } else if options.cache_only {
+                let previous = options.previous_runs.get(list_type);
+                uncached.extend(uncached_ids(&entries.anilist_id, previous));
+                Ok(previous
+                    .map(|previous| merge_details(HashMap::new(), previous))
+                    .unwrap_or_default())
+            } else if let Some(previous) = options.previous_runs.get(list_type) {
+                // Only fetch titles that are new or were updated since the previous run.
+                let stale = stale_ids(&entries.anilist_id, &entries.updated_at, previous);
+                fetch_avg_scores(&stale).map(|fetched| merge_details(fetched, previous))
+            } else {
+                fetch_avg_scores(&entries.anilist_id)
+            };
+            avg_fetch_time += avg_fetch_start.elapsed();
+            let avg_scores = match avg_scores {
+                Err(
+                    err @ (AnilistError::RuntimeExceeded { .. }
+                    | AnilistError::Unauthorized(_)
+                    | AnilistError::RateLimited),
+                ) => return Err(err),
+                Err(err) => {
+                    eprintln!(
+                        "Warning: global scores of list '{list_type}' could not be fetched ({err}). Skipping it."
+                    );
+                    continue;
+                }
+                Ok(avg_scores) => avg_scores,
+            };
+
+            let avg_scores: Vec<MediaDetails> = entries
+                .anilist_id
+                .iter()
+                .map(|id| avg_scores.get(id).cloned().unwrap_or_default())
+                .collect();
+            let mut aniscores = AnilistScores {
+                list_type: list_type.to_string(),
+                media_type: media.to_string(),
+                anilist_id: entries.anilist_id,
+                title: avg_scores
+                    .iter()
+                    .map(|details| details.title.preferred(options.title_lang))
+                    .collect(),
+                user_score: entries.user_score,
+                global_avg_score: avg_scores
+                    .iter()
+                    .map(|details| details.global_score)
+                    .collect(),
+                updated_at: entries.updated_at,
+                status: entries.status,
+                notes: entries.notes,
+                repeat: entries.repeat,
+                progress: entries.progress,
+                length: entries.length,
+                release_year: avg_scores.iter().map(|details| details.year).collect(),
+                // Scores were converted to the 100-point scale when parsed.
+                score_scale: entries.score_format.map(|_| 1),
+                genres: avg_scores
+                    .iter()
+                    .map(|details| details.genres.clone())
+                    .collect(),
+                tags: avg_scores
+                    .iter()
+                    .map(|details| details.tags.clone())
+                    .collect(),
+                airing_status: avg_scores
+                    .iter()
+                    .map(|details| details.airing_status.clone())
+                    .collect(),
+            };
+            if options.exclude_unreleased {
+                aniscores.exclude_unreleased();
+            }
+            anilist_scores.push(aniscores);
+        }
+    }
+
+    if !uncached.is_empty() {
+        uncached.sort_unstable();
+        return Err(AnilistError::NotCached(uncached));
+    }
+
+    // Fixed order whatever order the lists and entries were fetched in.
+    anilist_scores.sort_by(|a, b| a.list_type.cmp(&b.list_type));
+    for scores in anilist_scores.iter_mut() {
+        scores.sort_by_id();
+    }
+    disambiguate_list_names(&mut anilist_scores);
+
+    if options.warn_threshold > 0.0 && !interrupted() {
+        // Only a sanity check, so failing to fetch the statistics is not an error.
+        if let Ok(counts) = get_status_counts(&client, username, media) {
+            let expected = options
+                .expected_statuses()
+                .iter()
+                .filter_map(|status| counts.get(status))
+                .sum();
+            if let Some(warning) = yield_warning(entries_fetched, expected, options.warn_threshold)
+            {
+                eprintln!("{warning}");
+            }
+        }
+    }
+
+    if options.timing {
+        eprintln!("List fetch took {:.2}s.", list_fetch_time.as_secs_f64());
+        eprintln!(
+            "Average score fetch took {:.2}s.",
+            avg_fetch_time.as_secs_f64()
+        );
+    }
+    Ok(anilist_scores)
+}
+
+/// Run the command given on the command line, exiting with an error status on failure.
+pub fn run() {
+    let cli = Cli::parse_from(cli::with_default_command(args()));
+    match cli.command {
+        Command::Fetch(args) => fetch(*args),
+        Command::Compare(args) => compare(args),
+        Command::Diff(args) => {
+            if let Err(err) = diff::diff_files(&args.old, &args.new, &args.output) {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+        }
+        Command::Check(args) => check(args),
+        Command::Rescore(args) => rescore(args),
+    }
+}
+
+fn check(args: CheckArgs) {
+    let token = args
+        .token(env::var("ANILIST_TOKEN").ok())
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1)
+        });
+    let client = args.network.client().with_token(token);
+    match run_check(&client) {
+        Ok(check) => {
+            println!("Anilist is reachable.");
+            if let Some(remaining) = check.rate_limit_remaining {
+                println!("Rate limit remaining: {remaining}");
+            }
+            match check.viewer {
+                Ok(name) => println!("Token accepted for {name}."),
+                Err(err) => {
+                    eprintln!("Token rejected: {err}");
+                    process::exit(1);
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("Anilist is unreachable: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn rescore(args: RescoreArgs) {
+    let media = args.media.to_uppercase();
+    let client = args.network.client();
+
+    let ids = rescore::read_ids(io::stdin().lock());
+    let details = run_query_avg_scores(
+        &client,
+        &media,
+        &ids,
+        args.mean_basis,
+        args.chunk_size.unwrap_or_else(|| max_chunk_size(false)),
+        false,
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1)
+    });
+    let mut stdout = io::stdout().lock();
+    for line in rescore::score_lines(&ids, &details) {
+        writeln!(stdout, "{line}").expect("Unable to write scores to stdout.");
+    }
+}
+
+fn compare(args: CompareArgs) {
+    let media = args.media.to_uppercase();
+    let options = QueryOptions {
+        no_global: true,
+        retry: args.network.retry_policy(),
+        user_agent: args.network.user_agent.clone(),
+        endpoints: args.network.endpoint.clone(),
+        pool_size: args.network.pool_size,
+        ..Default::default()
+    };
+    let fetch_user = |username: &str| {
+        get_anilist_scores(username, &media, &options).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1)
+        })
+    };
+    let comparison = compare::compare_users(&fetch_user(&args.username), &fetch_user(&args.other));
+
+    println!(
+        "{} and {} both scored {} titles.",
+        args.username, args.other, comparison.shared
+    );
+    if comparison.shared == 0 {
+        return;
+    }
+    let precision = args.precision;
+    println!(
+        "Mean score: {} {}, {} {}. Mean absolute difference: {}.",
+        args.username,
+        format_float(comparison.mean_score, precision, false),
+        args.other,
+        format_float(comparison.other_mean_score, precision, false),
+        format_float(comparison.mean_abs_difference, precision, false)
+    );
+    match comparison.correlation {
+        Some(correlation) => println!(
+            "Correlation: {}",
+            format_float(correlation, precision, false)
+        ),
+        None => println!("Correlation: undefined"),
+    }
+}
+
+fn fetch(args: FetchArgs) {
+    let username = args.username;
+    let media_type = args.media.to_uppercase();
+    let format = args.format;
+    let write_summary = args.summary_csv;
+    let include_favourites = args.include_favourites;
+    let to_stdout = args.stdout;
+    // Overwriting is the default, so --overwrite only matters by overriding --no-clobber.
+    let no_clobber = args.no_clobber;
+    let out_dir = output_dir(&args.output_dir, &media_type, args.group_by_media);
+    let out_path = |fname: String| out_dir.join(fname).to_string_lossy().to_string();
+    let export_path = args.from_export;
+    // Exports only hold the user's own scores, global scores can only come from the cache.
+    let no_global = args.no_global || (export_path.is_some() && !args.cache_only);
+    let timing = args.timing;
+    let pretty = args.pretty;
+    let precision = args.precision;
+    let metric = args.metric;
+    let ratio_basis = args.ratio_basis;
+    let writer_options = WriterOptions {
+        compact: args.compact,
+        include_delta: metric == Metric::Delta,
+        include_url: args.include_url,
+        include_notes: args.include_notes,
+        include_progress: args.include_progress,
+        include_airing_status: args.include_airing_status,
+        quote_style: args.quote_style,
+        display_scale: args.display_scale,
+        round_scores: args.round_scores,
+        ratio_basis,
+        precision,
+        utf8_bom: args.utf8_bom,
+        include_global: !no_global,
+        columns_order: args
+            .columns_order
+            // Already validated by clap.
+            .and_then(|order| parse_columns_order(&order).ok())
+            .unwrap_or_default(),
+    };
+    if args.print_schema {
+        let printed = writer_options.schema().and_then(|schema| {
+            let mut stdout = io::stdout().lock();
+            for (name, dtype) in schema.iter() {
+                writeln!(stdout, "{name}\t{dtype}")?;
+            }
+            Ok(())
+        });
+        if let Err(err) = printed {
+            eprintln!("Unable to print schema: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+    let score_writer = writer_for_format(format, writer_options);
+    if args.append && !["csv", "tsv"].contains(&score_writer.extension()) {
+        eprintln!(
+            "--append needs --format csv or tsv, not {}.",
+            score_writer.extension()
+        );
+        process::exit(1);
+    }
+    if args.incremental && score_writer.extension() != "json" {
+        eprintln!(
+            "--incremental needs --format json, not {}.",
+            score_writer.extension()
+        );
+        process::exit(1);
+    }
+    let previous_runs = if args.incremental {
+        load_previous_runs(&out_dir, &media_type, &username, args.display_scale).unwrap_or_else(
+            |err| {
+                eprintln!("{err}");
+                process::exit(1)
+            },
+        )
+    } else {
+        HashMap::new()
+    };
+    let options = QueryOptions {
+        retry_on_empty: args.retry_on_empty,
+        limit: args.limit,
+        sample: args.sample,
+        seed: args.seed,
+        basis: args.mean_basis,
+        exclude_private: args.exclude_private,
+        timing,
+        retry: args.network.retry_policy(),
+        chunk_size: args.chunk_size,
+        no_global,
+        title_lang: args.title_lang,
+        previous_runs,
+        user_agent: args.network.user_agent.clone(),
+        endpoints: args.network.endpoint.clone(),
+        pool_size: args.network.pool_size,
+        planning_report: args.planning_report && !no_global,
+        status_changes: args.include_status_changes,
+        warn_threshold: args.warn_threshold,
+        score_format: args.score_format,
+        max_entries: args.max_entries,
+        include_notes: args.include_notes,
+        include_progress: args.include_progress,
+        tags: args.by_tag,
+        statuses: args
+            .status
+            // Already validated by clap.
+            .and_then(|statuses| parse_statuses(&statuses).ok())
+            .unwrap_or_default(),
+        custom_lists: args.custom_list.clone(),
+        exclude_lists: args
+            .exclude_lists
+            .as_deref()
+            .map(parse_list_names)
+            .unwrap_or_default(),
+        cache_only: args.cache_only,
+        exclude_unreleased: args.exclude_unreleased,
+        on_invalid_score: args.on_invalid_score,
+        verbose: args.verbose,
+        deadline: args
+            .max_runtime_secs
+            .map(|secs| Deadline::after(Duration::from_secs(secs))),
+        transport: None,
+        requests: Arc::default(),
+    };
+    let trend = args.trend;
+    let trim = args.trim;
+    let normalize_global = args.normalize_global;
+    let recency_halflife = args.recency_halflife_days;
+    let run_at = Utc::now().timestamp();
+    let by_year = args.by_year;
+    let error_format = args.error_format;
+    if args.list_lists {
+        let lists =
+            get_media_lists(&options.client(), &username, &media_type).unwrap_or_else(|err| {
+                error_format.report(&err);
+                process::exit(1)
+            });
+        for list in lists {
+            let custom = if list.is_custom_list { " (custom)" } else { "" };
+            println!("{}{custom}: {} entries", list.name, list.entries.len());
+        }
+        return;
+    }
+    if args.stream {
+        if print_score_stream(&username, &media_type, &options, error_format) {
+            process::exit(1);
+        }
+        return;
+    }
+    if !to_stdout || write_summary || args.track_ratio {
+        if let Err(err) = fs::create_dir_all(&out_dir) {
+            eprintln!("Unable to create {}: {err}", out_dir.display());
+            process::exit(1);
+        }
+    }
+    install_interrupt_handler();
+    let mut anilist_scores = if let Some(path) = export_path {
+        let lists = export::from_export(&path).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1)
+        });
+        let mut lists: Vec<AnilistScores> = lists
+            .into_iter()
+            .filter(|score| {
+                score.media_type == media_type
+                    && !is_excluded(&score.list_type, &options.exclude_lists)
+            })
+            .collect();
+        if options.cache_only {
+            fill_from_cache(&mut lists, &options.previous_runs).unwrap_or_else(|err| {
+                error_format.report(&err);
+                process::exit(1)
+            });
+        }
+        lists
+    } else {
+        let lists = get_anilist_scores(&username, &media_type, &options).unwrap_or_else(|err| {
+            error_format.report(&err);
+            process::exit(1)
+        });
+        if options.verbose {
+            // Only context, so failing to fetch the statistics is not an error.
+            if let Ok(stats) = get_user_statistics(&options.client(), &username) {
+                eprintln!("{}", user_stats_line(&username, &media_type, &stats));
+            }
+        }
+        lists
+    };
+
+    // Planning entries are unscored, so they are reported apart from the scored lists.
+    let planning = anilist_scores
+        .iter()
+        .position(|score| score.list_type == PLANNING_LIST)
+        .map(|i| anilist_scores.remove(i));
+    // Lists only fetched for the status changes report aren't written or scored.
+    let status_change_lists: Vec<AnilistScores> =
+        if options.status_changes && options.statuses.is_empty() {
+            let (extra, scored) = anilist_scores
+                .into_iter()
+                .partition(|score| STATUS_CHANGE_LISTS.contains(&score.list_type.as_str()));
+            anilist_scores = scored;
+            extra
+        } else {
+            vec![]
+        };
+
+    // Keep stdout parseable when scores are written to it.
+    let mut report: Box<dyn Write> = if args.quiet {
+        Box::new(io::sink())
+    } else if to_stdout {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+
+    writeln!(
+        report,
+        "This script queries an Anilist profile and calculates a global average score.
+    - score <= 0.99 indicates contrarian taste.
+    - score = 1.0 indicates completely average taste.
+    - score >= 1.1 indicates contrarian taste.
+    "
+    )
+    .unwrap();
+
+    for score in anilist_scores.iter() {
+        if to_stdout {
+            if let Err(err) = score.to_stdout(score_writer.as_ref()) {
+                eprintln!("Unable to write scores to stdout: {err}");
+                process::exit(1);
+            }
+        } else {
+            let list_type = &score.list_type;
+            let extension = score_writer.extension();
+            let fname = output_path(
+                &out_path(format!(
+                    "anilist_{media_type}_{list_type}_score_{username}.{extension}"
+                )),
+                no_clobber,
+            );
+            let written = if args.append {
+                score
+                    .append_to_file(score_writer.as_ref(), &fname)
+                    .map_err(|err| format!("Unable to append to {fname}: {err}"))
+            } else {
+                score.to_file(score_writer.as_ref(), &fname)
+            };
+            match written {
+                // Catches titles dropped by the filters at a glance.
+                Ok(()) => writeln!(
+                    report,
+                    "Wrote {} titles to {fname}.",
+                    score.anilist_id.len()
+                )
+                .unwrap(),
+                Err(err) => eprintln!("{err}"),
+            }
+        }
+
+        if no_global {
+            writeln!(
+                report,
+                "Exported {} titles from '{}' series.",
+                score.anilist_id.len(),
+                score.list_type
+            )
+            .unwrap();
+            continue;
+        }
+        if let Some(warning) = score.missing_global_warning() {
+            writeln!(report, "{warning}\n").unwrap();
+            continue;
+        }
+
+        if !pretty {
+            match metric {
+                Metric::Ratio => writeln!(
+                    report,
+                    "Average-ness score for '{}' series: {}{}",
+                    score.list_type,
+                    format_float(score.ratio_on(ratio_basis), precision, false),
+                    match ratio_basis {
+                        RatioBasis::Aggregate => "",
+                        RatioBasis::Mean => " (mean of per-title ratios)",
+                    }
+                ),
+                Metric::Delta => writeln!(
+                    report,
+                    "Mean score delta for '{}' series: {}",
+                    score.list_type,
+                    format_float(score.mean_delta(), precision, true)
+                ),
+            }
+            .unwrap();
+
+            let comparison = score.compare_to_average();
+            writeln!(
+                report,
+                "Rated above average: {} ({:.1}%), below: {} ({:.1}%), equal: {} ({:.1}%)\n",
+                comparison.above,
+                comparison.percent(comparison.above),
+                comparison.below,
+                comparison.percent(comparison.below),
+                comparison.equal,
+                comparison.percent(comparison.equal)
+            )
+            .unwrap();
+        }
+
+        if let Some(percent) = trim {
+            match metric {
+                Metric::Ratio => writeln!(
+                    report,
+                    "Trimmed average-ness score for '{}' series: {} (untrimmed {}, {percent}% trimmed from each end)",
+                    score.list_type,
+                    format_float(score.trimmed_ratio(percent, ratio_basis), precision, false),
+                    format_float(score.ratio_on(ratio_basis), precision, false)
+                ),
+                Metric::Delta => writeln!(
+                    report,
+                    "Trimmed mean score delta for '{}' series: {} (untrimmed {}, {percent}% trimmed from each end)",
+                    score.list_type,
+                    format_float(score.trimmed_mean_delta(percent), precision, true),
+                    format_float(score.mean_delta(), precision, true)
+                ),
+            }
+            .unwrap();
+        }
+
+        if normalize_global {
+            match metric {
+                Metric::Ratio => writeln!(
+                    report,
+                    "Range-normalized average-ness score for '{}' series: {} (unnormalized {})",
+                    score.list_type,
+                    format_float(score.range_normalized_ratio(ratio_basis), precision, false),
+                    format_float(score.ratio_on(ratio_basis), precision, false)
+                ),
+                Metric::Delta => writeln!(
+                    report,
+                    "Range-normalized mean score delta for '{}' series: {} (unnormalized {})",
+                    score.list_type,
+                    format_float(score.range_normalized_mean_delta(), precision, true),
+                    format_float(score.mean_delta(), precision, true)
+                ),
+            }
+            .unwrap();
+        }
+
+        if let Some(halflife) = recency_halflife {
+            writeln!(
+                report,
+                "Recency-weighted average-ness score for '{}' series: {} (unweighted {}, {halflife} day half-life)",
+                score.list_type,
+                format_float(
+                    score.recency_weighted_ratio(halflife, run_at, ratio_basis),
+                    precision,
+                    false
+                ),
+                format_float(score.ratio_on(ratio_basis), precision, false)
+            )
+            .unwrap();
+        }
+
+        if let Some(bucket) = trend {
+            writeln!(
+                report,
+                "Average-ness trend for '{}' series:",
+                score.list_type
+            )
+            .unwrap();
+            for (label, count, ratio) in score.score_trend(bucket, ratio_basis) {
+                writeln!(
+                    report,
+                    "    {label}: {} ({count} titles)",
+                    format_float(ratio, precision, false)
+                )
+                .unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+
+        if by_year {
+            writeln!(
+                report,
+                "Average-ness by release year for '{}' series:",
+                score.list_type
+            )
+            .unwrap();
+            for (year, count, ratio) in score.score_by_year(ratio_basis) {
+                writeln!(
+                    report,
+                    "    {year}: {} ({count} titles)",
+                    format_float(ratio, precision, false)
+                )
+                .unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if pretty && !no_global {
+        let styled = if to_stdout {
+            io::stderr().is_terminal()
+        } else {
+            io::stdout().is_terminal()
+        };
+        let with_global: Vec<AnilistScores> = anilist_scores
+            .iter()
+            .filter(|score| score.missing_global_warning().is_none())
+            .cloned()
+            .collect();
+        write!(
+            report,
+            "{}",
+            pretty::summary_tables(&with_global, precision, ratio_basis, styled)
+        )
+        .unwrap();
+    }
+
+    if let Some(planning) = planning {
+        writeln!(
+            report,
+            "Planning: {} titles with a mean global score of {}.",
+            planning.anilist_id.len(),
+            format_float(planning.mean_global_score(), precision, false)
+        )
+        .unwrap();
+        for entry in planning.top_global(PLANNING_TOP_TITLES) {
+            writeln!(report, "    {}: {}", entry.title, entry.global_avg_score).unwrap();
+        }
+        writeln!(report).unwrap();
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if include_favourites {
+        let favourites =
+            get_favourite_scores(&username, &media_type, &options).unwrap_or_else(|err| {
+                error_format.report(&err);
+                process::exit(1)
+            });
+        let favourite_scores: Vec<i64> = favourites
+            .iter()
+            .map(|(_, score)| *score)
+            .filter(|score| *score != 0)
+            .collect();
+        let library_scores: Vec<i64> = anilist_scores
+            .iter()
+            .flat_map(|score| score.global_avg_score.iter().copied())
+            .filter(|score| *score != 0)
+            .collect();
+        let favourites_mean = mean(&favourite_scores);
+        let library_mean = mean(&library_scores);
+
+        writeln!(
+            report,
+            "Favourites: {} titles with a mean global score of {}, {} against the {} of your scored lists.",
+            favourites.len(),
+            format_float(favourites_mean, precision, false),
+            format_float(favourites_mean - library_mean, precision, true),
+            format_float(library_mean, precision, false)
+        )
+        .unwrap();
+        if favourites_mean > library_mean {
+            writeln!(
+                report,
+                "Your favourites skew higher than your scored lists.\n"
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                report,
+                "Your favourites do not skew higher than your scored lists.\n"
+            )
+            .unwrap();
+        }
+    }
+
+    let conformity = overall_conformity(&anilist_scores).filter(|_| !no_global);
+    if let Some(conformity) = conformity {
+        writeln!(
+            report,
+            "Conformity index: {} (mean distance from the global average in points, lower is more average)\n",
+            format_float(conformity, precision, false)
+        )
+        .unwrap();
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if write_summary {
+        let fname = output_path(
+            &out_path(format!("anilist_{media_type}_summary_{username}.csv")),
+            no_clobber,
+        );
+        let written = summary_dataframe(&anilist_scores, &media_type)
+            .and_then(|mut summary_df| write_csv(&fname, &mut summary_df));
+        if let Err(err) = written {
+            eprintln!("{err}");
+        }
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if options.status_changes {
+        let lists: Vec<AnilistScores> = anilist_scores
+            .iter()
+            .chain(status_change_lists.iter())
+            .cloned()
+            .collect();
+        let counts = engagement_counts(&lists);
+        writeln!(report, "Status changes:").unwrap();
+        for count in counts.iter() {
+            writeln!(
+                report,
+                "    {}: {} titles, mean delta {}",
+                count.engagement.label(),
+                count.titles,
+                count
+                    .mean_delta
+                    .map(|delta| format_float(delta, precision, true))
+                    .unwrap_or_else(|| "n/a".to_string())
+            )
+            .unwrap();
+        }
+        writeln!(report).unwrap();
+
+        if args.status_changes_csv {
+            let fname = output_path(
+                &out_path(format!(
+                    "anilist_{media_type}_status_changes_{username}.csv"
+                )),
+                no_clobber,
+            );
+            let written = engagement_dataframe(&counts)
+                .and_then(|mut counts_df| write_csv(&fname, &mut counts_df));
+            if let Err(err) = written {
+                eprintln!("{err}");
+            }
+        }
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if args.compare_media {
+        let other_media = if media_type == "ANIME" {
+            "MANGA"
+        } else {
+            "ANIME"
+        };
+        // Only the scored lists of the other media type, without this run's previous runs.
+        let other_options = QueryOptions {
+            previous_runs: HashMap::new(),
+            planning_report: false,
+            status_changes: false,
+            ..options.clone()
+        };
+        match get_anilist_scores(&username, other_media, &other_options) {
+            Ok(other_scores) => {
+                let ratios = [
+                    (
+                        media_type.as_str(),
+                        overall_ratio(&anilist_scores, ratio_basis),
+                    ),
+                    (other_media, overall_ratio(&other_scores, ratio_basis)),
+                ];
+                for (media, ratio) in ratios {
+                    writeln!(
+                        report,
+                        "Overall {} average-ness: {}",
+                        media.to_lowercase(),
+                        format_float(ratio, precision, false)
+                    )
+                    .unwrap();
+                }
+                let verdict = match more_contrarian_media(ratios[0], ratios[1]) {
+                    Some(media) => {
+                        format!("You are more contrarian about {}.", media.to_lowercase())
+                    }
+                    None => "You are as contrarian about both.".to_string(),
+                };
+                writeln!(report, "{verdict}\n").unwrap();
+            }
+            Err(err) => error_format.report(&err),
+        }
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if args.track_ratio && !no_global {
+        let fname = out_path(format!("anilist_{media_type}_ratio_{username}.json"));
+        // Always aggregate, so snapshots stay comparable whatever --ratio-basis was used.
+        let ratio = overall_ratio(&anilist_scores, RatioBasis::Aggregate);
+        match load_ratio_snapshot(&fname) {
+            Ok(Some(previous)) => {
+                let since = Utc
+                    .timestamp_opt(previous.run_at, 0)
+                    .single()
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+                let trend = match more_contrarian(previous.ratio, ratio) {
+                    Some(true) => "more contrarian",
+                    Some(false) => "less contrarian",
+                    None => "as contrarian as before",
+                };
+                writeln!(
+                    report,
+                    "Overall average-ness: {}, was {} on {since}: {trend}.",
+                    format_float(ratio, precision, false),
+                    format_float(previous.ratio, precision, false)
+                )
+                .unwrap();
+                if let (Some(conformity), Some(previous)) = (conformity, previous.conformity) {
+                    writeln!(
+                        report,
+                        "Conformity index: {}, was {} on {since}.",
+                        format_float(conformity, precision, false),
+                        format_float(previous, precision, false)
+                    )
+                    .unwrap();
+                }
+            }
+            Ok(None) => writeln!(
+                report,
+                "Overall average-ness: {}. Saved for comparison with the next run.",
+                format_float(ratio, precision, false)
+            )
+            .unwrap(),
+            Err(err) => eprintln!("{err}"),
+        }
+        if ratio.is_finite() {
+            let snapshot = RatioSnapshot {
+                run_at,
+                ratio,
+                conformity,
+            };
+            if let Err(err) = save_ratio_snapshot(&fname, &snapshot) {
+                eprintln!("{err}");
+            }
+        }
+    }
+
+    exit_if_interrupted(&anilist_scores);
+    if let Some(path) = &args.sqlite {
+        match sqlite::write_scores(path, &username, &anilist_scores, run_at) {
+            Ok(rows) => writeln!(report, "Wrote {rows} titles to {path}.").unwrap(),
+            Err(err) => eprintln!("Unable to write scores to {path}: {err}"),
+        }
+    }
+
+    let write_bias = |biases: &[bias::Bias], label: &str| {
+        let fname = output_path(&out_path(format!("{label}_bias.csv")), no_clobber);
+        let written =
+            bias_dataframe(biases, label).and_then(|mut bias_df| write_csv(&fname, &mut bias_df));
+        if let Err(err) = written {
+            eprintln!("{err}");
+        }
+    };
+    exit_if_interrupted(&anilist_scores);
+    if args.genre_bias {
+        if no_global {
+            eprintln!("Skipping --genre-bias, it needs global scores.");
+        } else {
+            write_bias(&genre_bias(&anilist_scores), "genre");
+        }
+    }
+    exit_if_interrupted(&anilist_scores);
+    if args.by_tag {
+        if no_global {
+            eprintln!("Skipping --by-tag, it needs global scores.");
+        } else {
+            let biases = tag_bias(&anilist_scores, args.tag_min_rank);
+            write_bias(&biases, "tag");
+            writeln!(report, "Tags you are most contrarian about:").unwrap();
+            for tag in most_contrarian(&biases, CONTRARIAN_TAGS) {
+                writeln!(
+                    report,
+                    "    {}: {} ({} titles)",
+                    tag.name,
+                    format_float(tag.bias, precision, true),
+                    tag.titles
+                )
+                .unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+    }
+
+    if timing || args.verbose {
+        writeln!(
+            report,
+            "Sent {} requests to Anilist.",
+            options.requests_made()
+        )
+        .unwrap();
+    }
+
+    exit_if_interrupted(&anilist_scores);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::tests::ok_response;
+    use proptest::prelude::*;
+
+    fn media_list(value: Value) -> MediaList {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn correlation_skips_unscored_titles() {
+        let scores = AnilistScores::fixture(vec![60, 70, 0, 90], vec![65, 75, 80, 95]);
+        assert!((scores.correlation().unwrap() - 1.0).abs() < 1e-9);
+
+        let constant = AnilistScores::fixture(vec![70, 70], vec![60, 80]);
+        assert_eq!(constant.correlation(), None);
+    }
+
+    #[test]
+    fn most_divergent_orders_by_absolute_delta() {
+        let scores = AnilistScores::fixture(vec![90, 40, 70, 0], vec![70, 75, 72, 50]);
+        let divergent: Vec<(i64, i64)> = scores
+            .most_divergent(2)
+            .into_iter()
+            .map(|(entry, delta)| (entry.anilist_id, delta))
+            .collect();
+
+        assert_eq!(divergent, vec![(2, -35), (1, 20)]);
+    }
+
+    #[test]
+    fn csv_write_errors_are_reported() {
+        let mut df = df!("list_type" => ["Completed"]).unwrap();
+        let dir = std::env::temp_dir().join(format!("anilist_missing_{}", std::process::id()));
+        let fname = dir.join("summary.csv").to_string_lossy().to_string();
+
+        let err = write_csv(&fname, &mut df).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Unable to create file at {fname}")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn top_global_is_highest_first() {
+        let scores = AnilistScores::fixture(vec![0, 0, 0], vec![70, 88, 81]);
+        let top: Vec<i64> = scores
+            .top_global(2)
+            .iter()
+            .map(|entry| entry.anilist_id)
+            .collect();
+
+        assert_eq!(top, vec![2, 3]);
+    }
+
+    #[test]
+    fn format_float_rounds_to_precision() {
+        assert_eq!(format_float(1.23456, 3, false), "1.235");
+        assert_eq!(format_float(0.5, 0, false), "0");
+        assert_eq!(format_float(4.0, 2, true), "+4.00");
+        assert_eq!(format_float(-1.25, 1, true), "-1.2");
+    }
+
+    fn out_of_range_entries() -> ListEntries {
+        parse_entry_values(
+            &media_list(json!({"name": "Completed", "entries": [
+                {"mediaId": 1, "score": 80},
+                {"mediaId": 2, "score": 110},
+                {"mediaId": 3, "score": -5}
+            ]})),
+            None,
+        )
+    }
+
+    #[test]
+    fn invalid_scores_are_kept_by_default() {
+        let mut entries = out_of_range_entries();
+        let warning = entries
+            .handle_invalid_scores(InvalidScore::default(), "Completed")
+            .unwrap();
+
+        assert!(warning.contains("2 scores in 'Completed'"));
+        assert!(warning.contains("ids 2, 3"));
+        assert_eq!(entries.user_score, vec![80, 110, -5]);
+    }
+
+    #[test]
+    fn invalid_scores_are_clamped() {
+        let mut entries = out_of_range_entries();
+        assert!(entries
+            .handle_invalid_scores(InvalidScore::Clamp, "Completed")
+            .is_some());
+
+        assert_eq!(entries.anilist_id, vec![1, 2, 3]);
+        assert_eq!(entries.user_score, vec![80, 100, 0]);
+    }
+
+    #[test]
+    fn invalid_scores_are_skipped() {
+        let mut entries = out_of_range_entries();
+        assert!(entries
+            .handle_invalid_scores(InvalidScore::Skip, "Completed")
+            .is_some());
+
+        assert_eq!(entries.anilist_id, vec![1]);
+        assert_eq!(entries.user_score, vec![80]);
+        assert_eq!(
+            entries.handle_invalid_scores(InvalidScore::Skip, "Completed"),
+            None
+        );
+        assert_eq!(InvalidScore::from_flag("CLAMP"), Some(InvalidScore::Clamp));
+        assert_eq!(InvalidScore::from_flag("warn"), None);
+    }
+
+    #[test]
+    fn status_filter_keeps_matching_entries() {
+        let mut entries = parse_entry_values(
+            &media_list(json!({"entries": [
+                {"mediaId": 1, "score": 80, "status": "COMPLETED"},
+                {"mediaId": 2, "score": 0, "status": "CURRENT"},
+                {"mediaId": 3, "score": 60, "status": "REPEATING"},
+            ]})),
+            None,
+        );
+        entries.with_status(&parse_statuses("completed, repeating").unwrap());
+
+        assert_eq!(entries.anilist_id, vec![1, 3]);
+        assert_eq!(entries.status, vec!["COMPLETED", "REPEATING"]);
+        assert!(parse_statuses("WATCHING").is_err());
+    }
+
+    #[test]
+    fn trimming_drops_outlier_deltas() {
+        let mut user = vec![70; 9];
+        user.push(10);
+        let list = AnilistScores::fixture(user, vec![70; 10]);
+
+        assert_eq!(list.trimmed_mean_delta(10.0), 0.0);
+        assert_eq!(list.mean_delta(), -6.0);
+        assert_eq!(list.trimmed_ratio(10.0, RatioBasis::Aggregate), 1.0);
+        assert_eq!(list.trimmed_ratio(10.0, RatioBasis::Mean), 1.0);
+        assert!(list.ratio() < 1.0);
+    }
+
+    #[test]
+    fn global_scores_are_rescaled_to_user_range() {
+        let list = AnilistScores::fixture(vec![6, 8, 10], vec![50, 70, 90]);
+
+        assert_eq!(
+            list.range_normalized_pairs(),
+            vec![(60.0, 60.0), (80.0, 80.0), (100.0, 100.0)]
+        );
+        assert_eq!(list.range_normalized_ratio(RatioBasis::Aggregate), 1.0);
+        assert_eq!(list.range_normalized_ratio(RatioBasis::Mean), 1.0);
+        assert_eq!(list.range_normalized_mean_delta(), 0.0);
+        assert_eq!(list.mean_delta(), 10.0);
+    }
+
+    #[test]
+    fn recent_titles_dominate_weighted_ratio() {
+        let now = 400 * 86_400;
+        let mut list = AnilistScores::fixture(vec![90, 40], vec![60, 80]);
+        list.updated_at = vec![now, now - 365 * 86_400];
+
+        assert!(list.recency_weighted_ratio(30.0, now, RatioBasis::Aggregate) > 1.4);
+        for basis in [RatioBasis::Aggregate, RatioBasis::Mean] {
+            let unweighted = list.ratio_on(basis);
+            assert!((list.recency_weighted_ratio(1e9, now, basis) - unweighted).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn all_zero_global_scores_warn() {
+        let response = json!({"query_0": {"averageScore": null}, "query_1": null});
+        let details = parse_avg_scores(&response, &[1, 2], ScoreBasis::Average).unwrap();
+        let global: Vec<i64> = [1, 2]
+            .iter()
+            .map(|id| details.get(id).cloned().unwrap_or_default().global_score)
+            .collect();
+        let list = AnilistScores::fixture(vec![80, 70], global);
+
+        assert!(list.ratio().is_infinite());
+        assert!(list.missing_global_warning().unwrap().contains("2 titles"));
+        assert_eq!(
+            AnilistScores::fixture(vec![80, 70], vec![0, 75]).missing_global_warning(),
+            None
+        );
+        assert_eq!(
+            AnilistScores::fixture(vec![], vec![]).missing_global_warning(),
+            None
+        );
+    }
+
+    #[test]
+    fn ratio_is_grouped_by_release_year() {
+        let mut list = AnilistScores::fixture(vec![80, 60, 90, 70], vec![80, 80, 60, 70]);
+        list.release_year = vec![2004, 2004, 1998, 0];
+
+        assert_eq!(
+            list.score_by_year(RatioBasis::Aggregate),
+            vec![
+                ("1998".to_string(), 1, 1.5),
+                ("2004".to_string(), 2, 140.0 / 160.0)
+            ]
+        );
+        assert_eq!(
+            list.score_by_year(RatioBasis::Mean)[1],
+            ("2004".to_string(), 2, (1.0 + 60.0 / 80.0) / 2.0)
+        );
+    }
+
+    #[test]
+    fn entries_are_chunked_in_order() {
+        let entries = parse_entry_values(
+            &media_list(json!({"entries": [
+                {"mediaId": 1, "score": 80},
+                {"mediaId": 2, "score": 70},
+                {"mediaId": 3, "score": 60},
+            ]})),
+            None,
+        );
+
+        let chunks = entries.chunks(2);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].anilist_id, vec![1, 2]);
+        assert_eq!(chunks[1].user_score, vec![60]);
+    }
+
+    #[test]
+    fn missing_custom_lists_are_noted() {
+        let lists = vec![
+            media_list(json!({"name": "Completed", "isCustomList": false, "entries": []})),
+            media_list(json!({"name": "Favorites", "isCustomList": true, "entries": []})),
+        ];
+        let custom_lists = vec!["Favorites".to_string(), "Guilty pleasures".to_string()];
+
+        assert_eq!(
+            visibility_notes(&lists, &custom_lists, &[], true),
+            vec![
+                "Visible lists: Completed, Favorites (custom)",
+                "Custom list 'Guilty pleasures' is private or doesn't exist. Skipping it.",
+            ]
+        );
+        assert_eq!(
+            visibility_notes(&lists, &custom_lists[..1], &[], false).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn misspelled_lists_suggest_the_closest_name() {
+        let lists = vec![
+            media_list(json!({"name": "Completed", "isCustomList": false, "entries": []})),
+            media_list(json!({"name": "Planning", "isCustomList": false, "entries": []})),
+            media_list(json!({"name": "Favourites", "isCustomList": true, "entries": []})),
+            media_list(json!({"name": "Guilty pleasures", "isCustomList": true, "entries": []})),
+        ];
+
+        assert_eq!(
+            visibility_notes(&lists, &["Faves".to_string()], &parse_list_names("planing,completed"), false),
+            vec![
+                "Custom list 'Faves' is private or doesn't exist. Skipping it. Did you mean 'Favourites'?",
+                "Excluded list 'planing' matches none of your lists. Did you mean 'Planning'?",
+            ]
+        );
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(closest_name("x", std::iter::empty()), None);
+    }
+
+    #[test]
+    fn contrarian_media_is_furthest_from_average() {
+        assert_eq!(
+            more_contrarian_media(("ANIME", 1.02), ("MANGA", 0.91)),
+            Some("MANGA")
+        );
+        assert_eq!(
+            more_contrarian_media(("ANIME", 1.2), ("MANGA", 0.9)),
+            Some("ANIME")
+        );
+        assert_eq!(
+            more_contrarian_media(("ANIME", 1.25), ("MANGA", 0.75)),
+            None
+        );
+        assert_eq!(
+            more_contrarian_media(("ANIME", 1.1), ("MANGA", f64::NAN)),
+            None
+        );
+    }
+
+    #[test]
+    fn mismatched_column_is_named() {
+        let mut list = AnilistScores::fixture(vec![90, 80], vec![86, 70]);
+        list.title.pop();
+
+        let err = list.as_dataframe().unwrap_err();
+        assert_eq!(err.kind(), "dataframe");
+        assert_eq!(
+            err.to_string(),
+            "Unable to build the scores dataframe: column title of 'Completed' has 1 values, but anilist_id has 2"
+        );
+    }
+
+    #[test]
+    fn releasing_title_keeps_partial_average() {
+        let response = json!({
+            "query_0": {"averageScore": 62, "status": "RELEASING"},
+            "query_1": {"averageScore": null, "status": "NOT_YET_RELEASED"},
+            "query_2": {"averageScore": 86, "status": "FINISHED"}
+        });
+        let details = parse_avg_scores(&response, &[1, 2, 3], ScoreBasis::Average).unwrap();
+        assert_eq!(details[&1].global_score, 62);
+        assert_eq!(details[&1].airing_status, "RELEASING");
+
+        let mut list = AnilistScores::fixture(vec![70, 80, 90], vec![62, 0, 86]);
+        list.airing_status = [1, 2, 3]
+            .iter()
+            .map(|id| details[id].airing_status.clone())
+            .collect();
+        list.exclude_unreleased();
+        assert_eq!(list.anilist_id, vec![1, 3]);
+        assert_eq!(list.airing_status, vec!["RELEASING", "FINISHED"]);
+        assert_eq!(list.ratio(), 160.0 / 148.0);
+    }
+
+    #[test]
+    fn cache_only_reports_uncached_ids() {
+        let cached = |global_avg_score| PreviousEntry {
+            updated_at: 0,
+            global_avg_score,
+            title: String::new(),
+            release_year: 2005,
+            ..Default::default()
+        };
+        let previous_runs = HashMap::from([(
+            "Completed".to_string(),
+            HashMap::from([(1, cached(86)), (2, cached(80))]),
+        )]);
+
+        let mut lists = vec![AnilistScores::fixture(vec![90, 70], vec![0, 0])];
+        fill_from_cache(&mut lists, &previous_runs).unwrap();
+        assert_eq!(lists[0].global_avg_score, vec![86, 80]);
+        assert_eq!(lists[0].release_year, vec![2005, 2005]);
+
+        let mut lists = vec![AnilistScores::fixture(vec![90, 70, 60], vec![0, 0, 0])];
+        lists[0].anilist_id = vec![5, 1, 3];
+        assert_eq!(
+            fill_from_cache(&mut lists, &previous_runs),
+            Err(AnilistError::NotCached(vec![3, 5]))
+        );
+    }
+
+    #[test]
+    fn global_scores_are_aligned_to_entries() {
+        let transport = client::tests::Canned::new(vec![
+            (
+                "MediaListCollection",
+                json!({"data": {"MediaListCollection": {"lists": [
+                    {"name": "Completed", "entries": [
+                        {"mediaId": 457, "score": 90}, {"mediaId": 387, "score": 85}
+                    ]}
+                ]}}}),
+            ),
+            (
+                "query_0",
+                json!({"data": {
+                    "query_0": {"averageScore": 80, "title": {"romaji": "Mushishi"}},
+                    "query_1": {"averageScore": 75, "title": {"romaji": "Haibane Renmei"}}
+                }}),
+            ),
+        ]);
+        let options = QueryOptions {
+            transport: Some(transport.clone()),
+            warn_threshold: 0.0,
+            ..Default::default()
+        };
+
+        let scores = get_anilist_scores("koisland", "ANIME", &options).unwrap();
+        assert_eq!(scores[0].anilist_id, vec![387, 457]);
+        assert_eq!(scores[0].user_score, vec![85, 90]);
+        assert_eq!(scores[0].global_avg_score, vec![75, 80]);
+        assert_eq!(scores[0].title, vec!["Haibane Renmei", "Mushishi"]);
+
+        let queries = transport.queries.lock().unwrap();
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[1]["variables"]["id_0"], json!(457));
+    }
+
+    #[test]
+    fn rejected_token_stops_the_average_score_fetch() {
+        let (url, requests) = client::tests::mock_server(vec![client::tests::response(
+            "401 Unauthorized",
+            &[],
+            r#"{"errors":[{"message":"Invalid token","status":401}]}"#,
+        )]);
+        let client = QueryOptions {
+            endpoints: vec![url],
+            ..Default::default()
+        }
+        .client();
+
+        let result = run_query_avg_scores(
+            &client,
+            "ANIME",
+            &[457, 387, 21],
+            ScoreBasis::default(),
+            1,
+            false,
+        );
+        assert!(matches!(result, Err(AnilistError::Unauthorized(_))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_global_scores_are_reported_or_propagated() {
+        let lists = json!({"data": {"MediaListCollection": {"lists": [
+            {"name": "Completed", "entries": [{"mediaId": 457, "score": 90}]}
+        ]}}});
+        let options = |transport| QueryOptions {
+            transport: Some(transport),
+            warn_threshold: 0.0,
+            ..Default::default()
+        };
+
+        // No canned average score response, so the only chunk fails and the list is skipped.
+        let transport = client::tests::Canned::new(vec![("MediaListCollection", lists.clone())]);
+        let scores = get_anilist_scores("koisland", "ANIME", &options(transport)).unwrap();
+        assert!(scores.is_empty());
+
+        let transport = client::tests::Canned::new(vec![
+            ("MediaListCollection", lists),
+            (
+                "query_0",
+                json!({"errors": [{"message": "Too Many Requests.", "status": 429}]}),
+            ),
+        ]);
+        assert!(matches!(
+            get_anilist_scores("koisland", "ANIME", &options(transport)),
+            Err(AnilistError::RateLimited)
+        ));
+    }
+
+    #[test]
+    fn excluded_lists_are_dropped_after_picking() {
+        let lists = r#"{"data":{"MediaListCollection":{"lists":[
+            {"name":"Watching","isCustomList":false,"entries":[{"mediaId":10,"score":80}]},
+            {"name":"Completed","isCustomList":false,"entries":[{"mediaId":457,"score":90}]}
+        ]}}}"#;
+        let (url, requests) =
+            client::tests::mock_server(vec![ok_response(lists), ok_response(r#"{"data":{}}"#)]);
+        let options = QueryOptions {
+            endpoints: vec![url],
+            exclude_lists: parse_list_names(" watching ,,DROPPED"),
+            ..Default::default()
+        };
+
+        let scores = get_anilist_scores("koisland", "ANIME", &options).unwrap();
+        assert_eq!(options.exclude_lists, vec!["watching", "dropped"]);
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].list_type, "Completed");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert!(is_excluded(" Dropped", &options.exclude_lists));
+    }
+
+    #[test]
+    fn lists_and_entries_are_sorted() {
+        let lists = r#"{"data":{"MediaListCollection":{"lists":[
+            {"name":"Watching","isCustomList":false,"entries":[
+                {"mediaId":30,"score":70},{"mediaId":10,"score":80}]},
+            {"name":"Completed","isCustomList":false,"entries":[
+                {"mediaId":457,"score":90},{"mediaId":387,"score":85},{"mediaId":400,"score":60}]}
+        ]}}}"#;
+        let run = || {
+            // The list query, then one average score query per list.
+            let (url, _) = client::tests::mock_server(vec![
+                ok_response(lists),
+                ok_response(r#"{"data":{}}"#),
+                ok_response(r#"{"data":{}}"#),
+            ]);
+            let options = QueryOptions {
+                endpoints: vec![url],
+                ..Default::default()
+            };
+            get_anilist_scores("koisland", "ANIME", &options)
+                .unwrap()
+                .into_iter()
+                .map(|list| (list.list_type, list.anilist_id, list.user_score))
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert_eq!(
+            first,
+            vec![
+                (
+                    "Completed".to_string(),
+                    vec![387, 400, 457],
+                    vec![85, 60, 90]
+                ),
+                ("Watching".to_string(), vec![10, 30], vec![80, 70]),
+            ]
+        );
+        assert_eq!(run(), first);
+    }
+
+    #[test]
+    fn user_statistics_are_typed() {
+        let body = r#"{"data":{"User":{"statistics":{
+            "anime":{"count":412,"meanScore":74.4,"minutesWatched":98765},
+            "manga":{"count":37,"meanScore":0,"chaptersRead":null}
+        }}}}"#;
+        let (url, requests) = client::tests::mock_server(vec![ok_response(body)]);
+        let client = QueryOptions {
+            endpoints: vec![url],
+            ..Default::default()
+        }
+        .client();
+
+        let stats = get_user_statistics(&client, "koisland").unwrap();
+        assert!(requests.lock().unwrap()[0].contains("koisland"));
+        assert_eq!(stats.anime.count, 412);
+        assert_eq!(stats.anime.minutes_watched, 98765);
+        assert_eq!(stats.of("MANGA").chapters_read, 0);
+        assert_eq!(
+            user_stats_line("koisland", "ANIME", &stats),
+            "koisland has 412 anime entries with a mean score of 74.4 and 98765 minutes watched."
+        );
+    }
+
+    #[test]
+    fn total_entries_skips_custom_lists() {
+        let lists = vec![
+            media_list(json!({"name": "Completed", "entries": [{"mediaId": 1}, {"mediaId": 2}]})),
+            media_list(json!({"name": "Watching", "entries": [{"mediaId": 3}]})),
+            media_list(json!({"name": "Favs", "isCustomList": true, "entries": [{"mediaId": 1}]})),
+        ];
+
+        assert_eq!(total_entries(&lists), 3);
+    }
+
+    #[test]
+    fn duplicate_list_names_are_suffixed() {
+        let mut lists = vec![
+            AnilistScores::fixture(vec![80], vec![70]),
+            AnilistScores::fixture(vec![60], vec![75]),
+            AnilistScores::fixture(vec![90], vec![85]),
+        ];
+        lists[1].list_type = "Watching".to_string();
+        disambiguate_list_names(&mut lists);
+
+        let names: Vec<&str> = lists.iter().map(|list| list.list_type.as_str()).collect();
+        assert_eq!(names, vec!["Completed", "Watching", "Completed-2"]);
+    }
+
+    #[test]
+    fn unwritable_file_is_an_error() {
+        let list = AnilistScores::fixture(vec![80], vec![70]);
+        let fname = std::env::temp_dir()
+            .join(format!("anilist_missing_{}", std::process::id()))
+            .join("scores.csv");
+
+        let err = list.to_csv(&fname.to_string_lossy()).unwrap_err();
+        assert!(err.starts_with("Unable to create file at"));
+    }
+
+    #[test]
+    fn excluded_lists_are_not_expected() {
+        let options = QueryOptions {
+            exclude_lists: parse_list_names("Watching"),
+            planning_report: true,
+            ..Default::default()
+        };
+        assert_eq!(options.expected_statuses(), vec!["COMPLETED", "PLANNING"]);
+
+        let options = QueryOptions {
+            statuses: vec!["COMPLETED".to_string(), "REPEATING".to_string()],
+            exclude_lists: parse_list_names("rewatching,Favourites"),
+            ..Default::default()
+        };
+        assert_eq!(options.expected_statuses(), vec!["COMPLETED"]);
+    }
+
+    #[test]
+    fn low_yield_warns_below_threshold() {
+        assert!(yield_warning(40, 100, 0.5).is_some());
+        assert_eq!(yield_warning(50, 100, 0.5), None);
+        assert_eq!(yield_warning(0, 0, 0.5), None);
+    }
+
+    #[test]
+    fn too_complex_chunks_are_halved() {
+        let media_ids: Vec<i64> = (1..=24).collect();
+        let mut sizes = vec![];
+        let fetched = fetch_chunks(&media_ids, 24, |chunk| {
+            sizes.push(chunk.len());
+            if chunk.len() > 6 {
+                return Err(AnilistError::QueryTooComplex(
+                    "Max query complexity".to_string(),
+                ));
+            }
+            Ok(chunk
+                .iter()
+                .map(|id| (*id, MediaDetails::default()))
+                .collect())
+        })
+        .unwrap();
+
+        assert_eq!(fetched.len(), 24);
+        assert_eq!(sizes, vec![24, 12, 6, 6, 12, 6, 6]);
+
+        // Chunks at the minimum size are not split further.
+        let too_small = fetch_chunks(&media_ids[..MIN_SPLIT_CHUNK_SIZE], 24, |_| {
+            Err(AnilistError::QueryTooComplex(
+                "Max query complexity".to_string(),
+            ))
+        });
+        assert!(matches!(too_small, Err(AnilistError::QueryTooComplex(_))));
+    }
+
+    #[test]
+    fn failed_chunk_only_loses_its_ids() {
+        let media_ids: Vec<i64> = (1..=10).collect();
+        let fetched = fetch_chunks(&media_ids, 3, |chunk| {
+            if chunk.contains(&5) {
+                return Err(AnilistError::Request("connection reset".to_string()));
+            }
+            Ok(chunk
+                .iter()
+                .map(|id| {
+                    (
+                        *id,
+                        MediaDetails {
+                            global_score: 60 + id,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect())
+        })
+        .unwrap();
+
+        let mut ids: Vec<i64> = fetched.keys().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 2, 3, 7, 8, 9, 10]);
+        assert_eq!(fetched[&7].global_score, 67);
+
+        let all_failed = fetch_chunks(&media_ids, 5, |_| {
+            Err(AnilistError::Request("connection reset".to_string()))
+        });
+        assert_eq!(
+            all_failed,
+            Err(AnilistError::Request("connection reset".to_string()))
+        );
+
+        // Retries are spent by now, so the other chunks would be rate limited too.
+        let mut calls = 0;
+        let rate_limited = fetch_chunks(&media_ids, 3, |_| {
+            calls += 1;
+            Err(AnilistError::RateLimited)
+        });
+        assert_eq!(rate_limited, Err(AnilistError::RateLimited));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn score_format_normalizes_to_point_100() {
+        let list = media_list(json!({"entries": [
+            {"mediaId": 1, "score": 4.9},
+            {"mediaId": 2, "score": 3},
+        ]}));
+
+        let decimal = parse_entry_values(&list, Some(ScoreFormat::Point10Decimal));
+        assert_eq!(decimal.user_score, vec![49, 30]);
+        let smileys = parse_entry_values(&list, Some(ScoreFormat::Point3));
+        assert_eq!(smileys.user_score[1], 100);
+        // A decimal score gives the format away.
+        assert_eq!(parse_entry_values(&list, None).user_score, vec![49, 30]);
+        assert_eq!(ScoreFormat::from_flag("point_5"), Some(ScoreFormat::Point5));
+        assert_eq!(ScoreFormat::from_flag("POINT_7"), None);
+    }
+
+    #[test]
+    fn decimal_scores_are_kept_without_a_format() {
+        let list = media_list(json!({"entries": [
+            {"mediaId": 1, "score": 7.5},
+            {"mediaId": 2, "score": 8},
+        ]}));
+
+        let entries = parse_entry_values(&list, None);
+        assert_eq!(entries.anilist_id, vec![1, 2]);
+        assert_eq!(entries.user_score, vec![75, 80]);
+        assert_eq!(entries.score_format, Some(ScoreFormat::Point10Decimal));
+
+        let integers = media_list(json!({"entries": [{"mediaId": 1, "score": 8}]}));
+        assert_eq!(parse_entry_values(&integers, None).score_format, None);
+    }
+
+    #[test]
+    fn declared_score_format_conflicting_with_scores_warns() {
+        let list = media_list(json!({"entries": [
+            {"mediaId": 1, "score": 85},
+            {"mediaId": 2, "score": 0},
+        ]}));
+        let scores = raw_scores(&list);
+
+        let warning = ScoreFormat::Point10.conflict_warning("Completed", &scores);
+        assert!(warning.unwrap().contains("up to 85"));
+        assert_eq!(
+            ScoreFormat::Point100.conflict_warning("Completed", &scores),
+            None
+        );
+        assert!(ScoreFormat::Point100
+            .conflict_warning("Completed", &[7.5, 9.0])
+            .is_some());
+        assert_eq!(
+            ScoreFormat::Point10Decimal.conflict_warning("Completed", &[7.5, 0.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn appending_writes_header_once() {
+        let fname = std::env::temp_dir()
+            .join(format!("anilist_append_{}.csv", process::id()))
+            .to_string_lossy()
+            .to_string();
+        let writer = CsvScoreWriter {
+            delimiter: b',',
+            options: WriterOptions::default(),
+        };
+        let list = AnilistScores::fixture(vec![80], vec![70]);
+        list.append_to_file(&writer, &fname).unwrap();
+        list.append_to_file(&writer, &fname).unwrap();
+
+        let contents = fs::read_to_string(&fname).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert_eq!(contents.matches("list_type").count(), 1);
+        fs::remove_file(&fname).unwrap();
+    }
+
+    #[test]
+    fn group_by_media_uses_subdirectory() {
+        assert_eq!(output_dir("out", "ANIME", true), Path::new("out/anime"));
+        assert_eq!(output_dir("out", "MANGA", false), Path::new("out"));
+    }
+
+    #[test]
+    fn no_clobber_picks_free_suffix() {
+        let dir = std::env::temp_dir().join(format!("anilist_no_clobber_{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fname = dir.join("scores.csv").to_string_lossy().to_string();
+
+        assert_eq!(output_path(&fname, true), fname);
+        File::create(&fname).unwrap();
+        assert_eq!(output_path(&fname, false), fname);
+        assert_eq!(
+            output_path(&fname, true),
+            dir.join("scores-1.csv").to_string_lossy()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn avg_scores_are_keyed_by_id_regardless_of_order() {
+        let media_ids: Vec<i64> = (100..112).collect();
+        let response = |order: &[usize]| {
+            let fields: serde_json::Map<String, Value> = order
+                .iter()
+                .map(|i| {
+                    (
+                        format!("query_{i}"),
+                        json!({"averageScore": *i as i64 + 50}),
+                    )
+                })
+                .collect();
+            Value::Object(fields)
+        };
+
+        let in_order = parse_avg_scores(
+            &response(&(0..12).collect::<Vec<usize>>()),
+            &media_ids,
+            ScoreBasis::Average,
+        )
+        .unwrap();
+        let shuffled = parse_avg_scores(
+            &response(&[10, 2, 11, 0, 5, 1, 9, 3, 7, 4, 8, 6]),
+            &media_ids,
+            ScoreBasis::Average,
+        )
+        .unwrap();
+        assert_eq!(in_order, shuffled);
+        assert_eq!(shuffled[&102].global_score, 52);
+        assert_eq!(shuffled[&110].global_score, 60);
+
+        // Chunks completing in reverse order assemble to the same map.
+        let mut chunked =
+            parse_avg_scores(&response(&[1, 0]), &media_ids[6..], ScoreBasis::Average).unwrap();
+        chunked.extend(
+            parse_avg_scores(
+                &response(&[5, 3, 0, 2, 4, 1]),
+                &media_ids[..6],
+                ScoreBasis::Average,
+            )
+            .unwrap(),
+        );
+        assert_eq!(chunked[&106].global_score, 50);
+        assert_eq!(chunked[&103].global_score, 53);
+    }
+
+    /// A list entry as Anilist sends it, with any field missing or null and scores on
+    /// any of the formats.
+    fn arb_entry() -> impl Strategy<Value = Value> {
+        let score = prop_oneof![
+            (0..=100i64).prop_map(Value::from),
+            (0..=100i64).prop_map(|tenths| Value::from(tenths as f64 / 10.0)),
+        ];
+        (
+            prop::option::of(1..200_000i64),
+            prop::option::of(score),
+            prop::option::of(0..2_000_000_000i64),
+            prop::option::of(any::<bool>()),
+            prop::option::of(prop_oneof![
+                Just("CURRENT"),
+                Just("COMPLETED"),
+                Just("DROPPED")
+            ]),
+            prop::option::of("[a-z ]{0,12}"),
+            prop::option::of(0..5i64),
+            prop::option::of(0..2000i64),
+            prop::option::of(prop::option::of(1..2000i64)),
+        )
+            .prop_map(
+                |(id, score, updated_at, private, status, notes, repeat, progress, episodes)| {
+                    let mut entry = json!({
+                        "mediaId": id,
+                        "score": score,
+                        "updatedAt": updated_at,
+                        "private": private,
+                        "status": status,
+                        "notes": notes,
+                        "repeat": repeat,
+                        "progress": progress,
+                    });
+                    if let Some(episodes) = episodes {
+                        entry["media"] = json!({ "episodes": episodes });
+                    }
+                    entry
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn parse_entry_values_keeps_columns_aligned(
+            entries in prop::collection::vec(arb_entry(), 0..40),
+            score_format in prop::option::of(prop_oneof![
+                Just(ScoreFormat::Point100),
+                Just(ScoreFormat::Point10),
+                Just(ScoreFormat::Point10Decimal),
+            ]),
+        ) {
+            let scored = entries
+                .iter()
+                .filter(|entry| entry["mediaId"].is_i64() && entry["score"].is_number())
+                .count();
+            let list = media_list(json!({"name": "Completed", "entries": entries}));
+            let entries = parse_entry_values(&list, score_format);
+
+            prop_assert_eq!(entries.anilist_id.len(), scored);
+            prop_assert_eq!(entries.user_score.len(), scored);
+            prop_assert_eq!(entries.updated_at.len(), scored);
+            prop_assert_eq!(entries.private.len(), scored);
+            prop_assert_eq!(entries.status.len(), scored);
+            prop_assert_eq!(entries.notes.len(), scored);
+            prop_assert_eq!(entries.repeat.len(), scored);
+            prop_assert_eq!(entries.progress.len(), scored);
+            prop_assert_eq!(entries.length.len(), scored);
+        }
+    }
+}
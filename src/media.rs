@@ -0,0 +1,133 @@
+use serde::Deserialize;
+
+use crate::responses::Media;
+
+/// Which of a title's names fills the `title` column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TitleLanguage {
+    /// Anilist's own default.
+    #[default]
+    Romaji,
+    English,
+    Native,
+}
+
+impl TitleLanguage {
+    pub fn from_flag(value: &str) -> Option<TitleLanguage> {
+        match value.to_lowercase().as_str() {
+            "romaji" => Some(TitleLanguage::Romaji),
+            "english" => Some(TitleLanguage::English),
+            "native" => Some(TitleLanguage::Native),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct MediaTitle {
+    pub romaji: Option<String>,
+    pub english: Option<String>,
+    pub native: Option<String>,
+}
+
+impl MediaTitle {
+    /// Title in `lang`, falling back to romaji, then english, then native.
+    pub fn preferred(&self, lang: TitleLanguage) -> String {
+        let preferred = match lang {
+            TitleLanguage::Romaji => &self.romaji,
+            TitleLanguage::English => &self.english,
+            TitleLanguage::Native => &self.native,
+        };
+        preferred
+            .iter()
+            .chain(self.romaji.iter())
+            .chain(self.english.iter())
+            .chain(self.native.iter())
+            .next()
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Fields fetched per title by the average score query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaDetails {
+    /// Global score of the title on the requested basis. 0 if unknown.
+    pub global_score: i64,
+    pub title: MediaTitle,
+    pub genres: Vec<String>,
+    /// Tag names with their rank, 0 to 100. Empty unless requested.
+    pub tags: Vec<(String, i64)>,
+    /// `seasonYear` of anime, or the year manga started. 0 if unknown.
+    pub year: i64,
+    /// `MediaStatus` of the title, e.g. RELEASING. Empty if unknown.
+    pub airing_status: String,
+}
+
+impl MediaDetails {
+    /// Details of `media`, whose global score on the requested basis is `global_score`.
+    pub fn from_media(media: Media, global_score: Option<i64>) -> MediaDetails {
+        MediaDetails {
+            global_score: global_score.unwrap_or(0),
+            title: media.title,
+            genres: media.genres,
+            tags: media
+                .tags
+                .into_iter()
+                .map(|tag| (tag.name, tag.rank.unwrap_or(0)))
+                .collect(),
+            year: media
+                .season_year
+                .or_else(|| media.start_date.and_then(|date| date.year))
+                .unwrap_or(0),
+            airing_status: media.status.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn english_title_falls_back_to_romaji() {
+        let title: MediaTitle = serde_json::from_value(json!({
+            "romaji": "Shoujo Shuumatsu Ryokou",
+            "english": null,
+            "native": "少女終末旅行"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            title.preferred(TitleLanguage::English),
+            "Shoujo Shuumatsu Ryokou"
+        );
+        assert_eq!(title.preferred(TitleLanguage::Native), "少女終末旅行");
+    }
+
+    #[test]
+    fn manga_year_falls_back_to_start_date() {
+        let media = serde_json::from_value(json!({
+            "meanScore": 71,
+            "seasonYear": null,
+            "startDate": {"year": 1994}
+        }))
+        .unwrap();
+        let details = MediaDetails::from_media(media, Some(71));
+
+        assert_eq!(details.year, 1994);
+        assert_eq!(details.global_score, 71);
+    }
+
+    #[test]
+    fn missing_title_is_empty() {
+        let media: Media = serde_json::from_value(json!({"averageScore": 83})).unwrap();
+        let details = MediaDetails::from_media(media.clone(), media.average_score);
+
+        assert_eq!(details.global_score, 83);
+        assert_eq!(details.title.preferred(TitleLanguage::Romaji), "");
+        assert!(details.genres.is_empty());
+        assert_eq!(details.year, 0);
+    }
+}
@@ -0,0 +1,78 @@
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{Attribute, Cell, Table};
+
+use crate::{format_float, AnilistScores, RatioBasis};
+
+/// Titles listed under the summary table.
+pub const DIVERGENT_TITLES: usize = 5;
+
+fn table(header: &[&str], styled: bool) -> Table {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL);
+    if styled {
+        table.enforce_styling();
+    } else {
+        table.force_no_tty();
+    }
+    table.set_header(
+        header
+            .iter()
+            .map(|name| Cell::new(name).add_attribute(Attribute::Bold)),
+    );
+    table
+}
+
+/// Bordered table of per-list stats, followed by the titles that diverge most from the
+/// global average, with ratios on `basis`. `styled` enables bold headers and should only be
+/// set for a terminal.
+pub fn summary_tables(
+    anilist_scores: &[AnilistScores],
+    precision: usize,
+    basis: RatioBasis,
+    styled: bool,
+) -> String {
+    let mut summary = table(
+        &[
+            "list",
+            "titles",
+            "mean user score",
+            "mean global score",
+            "ratio",
+            "correlation",
+            "rank correlation",
+        ],
+        styled,
+    );
+    let mut divergent = table(
+        &["list", "title", "user score", "global score", "delta"],
+        styled,
+    );
+
+    for scores in anilist_scores {
+        summary.add_row(vec![
+            scores.list_type.clone(),
+            scores.anilist_id.len().to_string(),
+            format_float(scores.mean_user_score(), precision, false),
+            format_float(scores.mean_global_score(), precision, false),
+            format_float(scores.ratio_on(basis), precision, false),
+            scores
+                .correlation()
+                .map(|r| format_float(r, precision, false))
+                .unwrap_or_else(|| "-".to_string()),
+            scores
+                .spearman_correlation()
+                .map(|r| format_float(r, precision, false))
+                .unwrap_or_else(|| "-".to_string()),
+        ]);
+        for (entry, delta) in scores.most_divergent(DIVERGENT_TITLES) {
+            divergent.add_row(vec![
+                scores.list_type.clone(),
+                entry.title,
+                entry.user_score.to_string(),
+                entry.global_avg_score.to_string(),
+                format!("{delta:+}"),
+            ]);
+        }
+    }
+    format!("{summary}\n\nMost divergent titles:\n{divergent}\n")
+}
@@ -0,0 +1,277 @@
+use serde_json::{json, Map, Value};
+
+pub const QUERY_USER_MEDIA_SCORE: &str = "
+query ($username: String, $media: MediaType, $notes: Boolean = false, $progress: Boolean = false) {
+  MediaListCollection (userName: $username, type: $media) {
+    lists {
+        name
+        isCustomList
+        entries {
+            mediaId,
+            score,
+            updatedAt,
+            private,
+            status,
+            repeat,
+            notes @include(if: $notes)
+            progress @include(if: $progress)
+            media @include(if: $progress) {
+                episodes
+                chapters
+            }
+        }
+    }
+  }
+}
+";
+
+/// Names and entry ids of a user's lists, used by `--list-lists`.
+pub const QUERY_USER_LISTS: &str = "
+query ($username: String, $media: MediaType) {
+  MediaListCollection (userName: $username, type: $media) {
+    lists {
+        name
+        isCustomList
+        entries {
+            mediaId
+        }
+    }
+  }
+}
+";
+
+/// Smallest query that needs a valid token, used by `check`.
+pub const QUERY_VIEWER: &str = "
+query {
+  Viewer {
+    id,
+    name
+  }
+}
+";
+
+/// Query for a user's overall anime and manga statistics.
+pub const QUERY_USER_STATISTICS: &str = "
+query ($username: String) {
+  User (name: $username) {
+    statistics {
+      anime {
+        count,
+        meanScore,
+        minutesWatched
+      }
+      manga {
+        count,
+        meanScore,
+        chaptersRead
+      }
+    }
+  }
+}
+";
+
+/// Query for how many of a user's `media` (ANIME or MANGA) entries have each status.
+pub fn build_status_counts_query(media: &str) -> String {
+    let media = media.to_lowercase();
+    format!(
+        "
+query ($username: String) {{
+  User (name: $username) {{
+    statistics {{
+      {media} {{
+        statuses {{
+          status,
+          count
+        }}
+      }}
+    }}
+  }}
+}}
+"
+    )
+}
+
+/// Query for one page of a user's favourite `media` (ANIME or MANGA) with their `score_field`.
+pub fn build_favourites_query(media: &str, score_field: &str) -> String {
+    let media = media.to_lowercase();
+    format!(
+        "
+query ($username: String, $page: Int) {{
+  User (name: $username) {{
+    favourites {{
+      {media} (page: $page, perPage: 50) {{
+        pageInfo {{
+          hasNextPage
+        }}
+        nodes {{
+          id,
+          {score_field}
+        }}
+      }}
+    }}
+  }}
+}}
+"
+    )
+}
+
+/// Complexity Anilist allows for a single query.
+pub const MAX_QUERY_COMPLEXITY: usize = 500;
+
+/// Fields requested per aliased `Media`: itself, its score, `genres`, `status`,
+/// `seasonYear`, `startDate` and its year, `title` and its three names.
+const AVG_SCORE_FIELDS_PER_MEDIA: usize = 11;
+/// Fields added per aliased `Media` when tags are requested: `tags`, its name and rank.
+const TAG_FIELDS_PER_MEDIA: usize = 3;
+
+/// Rough complexity of an average score query for `id_count` media: one point for the query
+/// and one per field requested for each aliased `Media`.
+pub fn estimate_avg_score_complexity(id_count: usize, tags: bool) -> usize {
+    let tag_fields = if tags { TAG_FIELDS_PER_MEDIA } else { 0 };
+    1 + id_count * (AVG_SCORE_FIELDS_PER_MEDIA + tag_fields)
+}
+
+/// Alias of the `Media` field for the id at `index` in an average score query.
+pub fn avg_score_alias(index: usize) -> String {
+    format!("query_{index}")
+}
+
+/// Name of the variable holding the id at `index` in an average score query.
+fn avg_score_id_variable(index: usize) -> String {
+    format!("id_{index}")
+}
+
+/// Query fetching `score_field` for `id_count` media as aliased `Media` fields.
+/// Ids are passed as variables by `avg_score_variables`, never interpolated. Tags are only
+/// returned if the `tags` variable is set.
+pub fn build_avg_score_query(id_count: usize, score_field: &str) -> String {
+    let id_variables: String = (0..id_count)
+        .map(|i| format!(", ${}: Int", avg_score_id_variable(i)))
+        .collect();
+    let media_fields: String = (0..id_count)
+        .map(|i| {
+            let alias = avg_score_alias(i);
+            let id_variable = avg_score_id_variable(i);
+            format!(
+                "
+    {alias}: Media (id: ${id_variable}, type: $media) {{
+        {score_field}
+        genres
+        status
+        tags @include(if: $tags) {{
+            name
+            rank
+        }}
+        seasonYear
+        startDate {{
+            year
+        }}
+        title {{
+            romaji
+            english
+            native
+        }}
+    }}
+    "
+            )
+        })
+        .collect();
+
+    format!(
+        "
+    query ($media: MediaType, $tags: Boolean = false{id_variables}) {{
+        {media_fields}
+    }}
+    "
+    )
+}
+
+/// Variables for a query built by `build_avg_score_query`.
+pub fn avg_score_variables(media: &str, media_ids: &[i64]) -> Value {
+    let mut variables = Map::new();
+    variables.insert("media".to_string(), json!(media));
+    for (i, id) in media_ids.iter().enumerate() {
+        variables.insert(avg_score_id_variable(i), json!(id));
+    }
+    Value::Object(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avg_score_query_aliases_each_id() {
+        let query = build_avg_score_query(2, "averageScore");
+
+        assert!(query
+            .contains("query ($media: MediaType, $tags: Boolean = false, $id_0: Int, $id_1: Int)"));
+        assert!(query.contains("query_0: Media (id: $id_0, type: $media)"));
+        assert!(query.contains("query_1: Media (id: $id_1, type: $media)"));
+        assert_eq!(query.matches("averageScore").count(), 2);
+    }
+
+    #[test]
+    fn avg_score_query_has_balanced_braces() {
+        let query = build_avg_score_query(3, "meanScore");
+
+        assert_eq!(query.matches('{').count(), query.matches('}').count());
+        assert_eq!(query.matches('{').count(), 13);
+        assert_eq!(query.matches('(').count(), query.matches(')').count());
+    }
+
+    #[test]
+    fn avg_score_query_declares_every_used_variable() {
+        let query = build_avg_score_query(12, "averageScore");
+
+        for i in 0..12 {
+            assert_eq!(query.matches(&format!("$id_{i}:")).count(), 1);
+            assert_eq!(query.matches(&format!("$id_{i},")).count(), 1);
+        }
+    }
+
+    #[test]
+    fn avg_score_query_without_ids() {
+        let query = build_avg_score_query(0, "averageScore");
+
+        assert!(query.contains("query ($media: MediaType, $tags: Boolean = false)"));
+        assert!(!query.contains("Media ("));
+    }
+
+    #[test]
+    fn user_lists_query_skips_scores() {
+        assert!(!QUERY_USER_LISTS.contains("score"));
+        assert!(QUERY_USER_LISTS.contains("mediaId"));
+        assert_eq!(
+            QUERY_USER_LISTS.matches('{').count(),
+            QUERY_USER_LISTS.matches('}').count()
+        );
+    }
+
+    #[test]
+    fn favourites_query_selects_media() {
+        let query = build_favourites_query("MANGA", "meanScore");
+
+        assert!(query.contains("manga (page: $page, perPage: 50)"));
+        assert!(query.contains("meanScore"));
+        assert_eq!(query.matches('{').count(), query.matches('}').count());
+    }
+
+    #[test]
+    fn avg_score_complexity_grows_with_ids() {
+        assert_eq!(estimate_avg_score_complexity(0, false), 1);
+        assert_eq!(estimate_avg_score_complexity(40, false), 441);
+        assert_eq!(estimate_avg_score_complexity(10, true), 141);
+        assert!(estimate_avg_score_complexity(100, false) > MAX_QUERY_COMPLEXITY);
+    }
+
+    #[test]
+    fn avg_score_variables_hold_ids() {
+        let variables = avg_score_variables("ANIME", &[21, 1535]);
+
+        assert_eq!(
+            variables,
+            json!({"media": "ANIME", "id_0": 21, "id_1": 1535})
+        );
+    }
+}
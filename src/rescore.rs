@@ -0,0 +1,78 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::media::MediaDetails;
+
+/// Media id of one line of `--json-lines` input: a bare id, or a JSON object with an
+/// `anilist_id`, `mediaId` or `id` field. None for blank or unrecognized lines.
+pub fn parse_id_line(line: &str) -> Option<i64> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    match serde_json::from_str::<Value>(line).ok()? {
+        Value::Number(id) => id.as_i64(),
+        Value::Object(fields) => ["anilist_id", "mediaId", "id"]
+            .iter()
+            .find_map(|key| fields.get(*key).and_then(|id| id.as_i64())),
+        _ => None,
+    }
+}
+
+/// Ids of every recognized line of `input`, in order, warning about the rest.
+pub fn read_ids(input: impl BufRead) -> Vec<i64> {
+    let mut ids = vec![];
+    for (number, line) in input.lines().map_while(Result::ok).enumerate() {
+        match parse_id_line(&line) {
+            Some(id) => ids.push(id),
+            None if line.trim().is_empty() => {}
+            None => eprintln!("Skipping line {}: no media id in '{line}'.", number + 1),
+        }
+    }
+    ids
+}
+
+/// One NDJSON output line per id, with a null score for ids Anilist returned nothing for.
+pub fn score_lines(ids: &[i64], details: &HashMap<i64, MediaDetails>) -> Vec<String> {
+    ids.iter()
+        .map(|id| {
+            let score = details
+                .get(id)
+                .map(|details| details.global_score)
+                .filter(|score| *score != 0);
+            json!({"anilist_id": id, "global_avg_score": score}).to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_read_from_plain_and_ndjson_lines() {
+        let input = "21\n\n{\"anilist_id\": 457}\n{\"mediaId\": 387, \"score\": 80}\nnot an id\n";
+
+        assert_eq!(read_ids(input.as_bytes()), vec![21, 457, 387]);
+    }
+
+    #[test]
+    fn missing_scores_are_null() {
+        let details = HashMap::from([(
+            21,
+            MediaDetails {
+                global_score: 88,
+                ..Default::default()
+            },
+        )]);
+
+        assert_eq!(
+            score_lines(&[21, 99], &details),
+            vec![
+                r#"{"anilist_id":21,"global_avg_score":88}"#,
+                r#"{"anilist_id":99,"global_avg_score":null}"#
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::error::AnilistError;
+use crate::media::MediaTitle;
+
+/// Deserialize the part of a response at `path`, e.g. `data.MediaListCollection.lists`.
+pub fn parse<T: DeserializeOwned>(value: &Value, path: &str) -> Result<T, AnilistError> {
+    T::deserialize(value).map_err(|err| {
+        AnilistError::MalformedResponse(format!("Unexpected shape of {path}: {err}"))
+    })
+}
+
+/// `T::default()` for both a missing and a null field.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// `data.MediaListCollection` of `QUERY_USER_MEDIA_SCORE`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MediaListCollection {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub lists: Vec<MediaList>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaList {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub is_custom_list: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub entries: Vec<MediaListEntry>,
+}
+
+/// An entry of a `MediaList`. Every field is optional so one odd entry doesn't fail the
+/// whole list; entries without an id or score are skipped when parsed.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaListEntry {
+    pub media_id: Option<i64>,
+    /// Integer on the 100 and 10-point formats, decimal on POINT_10_DECIMAL.
+    pub score: Option<f64>,
+    pub updated_at: Option<i64>,
+    pub private: Option<bool>,
+    pub status: Option<String>,
+    /// Times the title was rewatched or reread.
+    pub repeat: Option<i64>,
+    pub notes: Option<String>,
+    /// Episodes watched or chapters read.
+    pub progress: Option<i64>,
+    /// Only present in list exports, or with the length of the title when progress is
+    /// requested.
+    pub media: Option<Media>,
+}
+
+/// An aliased `Media` of an average score query, or the `media` of an exported entry.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Media {
+    pub average_score: Option<i64>,
+    pub mean_score: Option<i64>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub title: MediaTitle,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub genres: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tags: Vec<MediaTag>,
+    pub season_year: Option<i64>,
+    pub start_date: Option<FuzzyDate>,
+    /// `MediaStatus`, e.g. FINISHED or RELEASING.
+    pub status: Option<String>,
+    /// Null for manga, and for anime still airing without a known count.
+    pub episodes: Option<i64>,
+    /// Null for anime, and for manga still running.
+    pub chapters: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MediaTag {
+    pub name: String,
+    /// How relevant the tag is to the title, 0 to 100.
+    pub rank: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FuzzyDate {
+    pub year: Option<i64>,
+}
+
+/// `data.User.statistics` of `QUERY_USER_STATISTICS`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct UserStats {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub anime: MediaStats,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub manga: MediaStats,
+}
+
+impl UserStats {
+    /// Statistics of `media`, ANIME or MANGA.
+    pub fn of(&self, media: &str) -> &MediaStats {
+        if media.eq_ignore_ascii_case("MANGA") {
+            &self.manga
+        } else {
+            &self.anime
+        }
+    }
+}
+
+/// Totals over every entry of one media type, scored or not.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaStats {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub count: i64,
+    /// On the 100-point scale, 0 if nothing is scored.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub mean_score: f64,
+    /// Always 0 for manga.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub minutes_watched: i64,
+    /// Always 0 for anime.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub chapters_read: i64,
+}
+
+/// An entry of `data.User.statistics.<anime|manga>.statuses` of a status counts query.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct StatusCount {
+    /// `MediaListStatus`, e.g. COMPLETED.
+    pub status: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub count: usize,
+}
+
+/// `data.User.favourites.<anime|manga>` of a favourites query, one page of it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FavouritesPage {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub nodes: Vec<Favourite>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub page_info: PageInfo,
+}
+
+/// A favourite title with its global scores.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Favourite {
+    pub id: Option<i64>,
+    #[serde(flatten)]
+    pub media: Media,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub has_next_page: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn nulls_read_as_empty() {
+        let list: MediaList = serde_json::from_value(json!({
+            "name": "Completed",
+            "isCustomList": null,
+            "entries": [{"mediaId": 1, "score": 4.5, "notes": null}]
+        }))
+        .unwrap();
+
+        assert!(!list.is_custom_list);
+        assert_eq!(list.entries[0].score, Some(4.5));
+        assert_eq!(list.entries[0].notes, None);
+
+        let media: Media =
+            serde_json::from_value(json!({"averageScore": 80, "genres": null, "title": null}))
+                .unwrap();
+        assert!(media.genres.is_empty());
+        assert_eq!(media.title, MediaTitle::default());
+    }
+
+    #[test]
+    fn favourites_page_reads_scores_and_paging() {
+        let page: FavouritesPage = parse(
+            &json!({
+                "nodes": [{"id": 457, "averageScore": 86}, {"id": null, "meanScore": 70}],
+                "pageInfo": {"hasNextPage": null}
+            }),
+            "favourites",
+        )
+        .unwrap();
+
+        assert_eq!(page.nodes[0].id, Some(457));
+        assert_eq!(page.nodes[0].media.average_score, Some(86));
+        assert_eq!(page.nodes[1].media.mean_score, Some(70));
+        assert!(!page.page_info.has_next_page);
+
+        let counts: Vec<StatusCount> =
+            parse(&json!([{"status": "COMPLETED", "count": 412}]), "statuses").unwrap();
+        assert_eq!(counts[0].count, 412);
+    }
+
+    #[test]
+    fn wrong_type_is_malformed() {
+        let err = parse::<Vec<MediaList>>(&json!([{"entries": "none"}]), "lists").unwrap_err();
+
+        assert!(
+            matches!(err, AnilistError::MalformedResponse(message) if message.contains("lists"))
+        );
+    }
+}
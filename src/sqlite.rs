@@ -0,0 +1,127 @@
+use rusqlite::{params, Connection};
+
+use crate::AnilistScores;
+
+/// `scores` holds the latest row of each title, `score_history` one row per title per run.
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS scores (
+    user TEXT NOT NULL,
+    media TEXT NOT NULL,
+    anilist_id INTEGER NOT NULL,
+    list_type TEXT NOT NULL,
+    title TEXT NOT NULL,
+    user_score INTEGER NOT NULL,
+    global_avg_score INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    release_year INTEGER NOT NULL,
+    notes TEXT NOT NULL,
+    run_at INTEGER NOT NULL,
+    PRIMARY KEY (user, media, anilist_id)
+);
+CREATE TABLE IF NOT EXISTS score_history (
+    user TEXT NOT NULL,
+    media TEXT NOT NULL,
+    anilist_id INTEGER NOT NULL,
+    list_type TEXT NOT NULL,
+    title TEXT NOT NULL,
+    user_score INTEGER NOT NULL,
+    global_avg_score INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    release_year INTEGER NOT NULL,
+    notes TEXT NOT NULL,
+    run_at INTEGER NOT NULL,
+    PRIMARY KEY (user, media, anilist_id, run_at)
+);
+";
+
+const COLUMNS: &str = "user, media, anilist_id, list_type, title, user_score, global_avg_score, \
+    updated_at, status, release_year, notes, run_at";
+
+/// Write the entries of `lists` to the database at `path`, creating it and its tables if
+/// absent. Each title's row in `scores` is replaced by this run's, keyed on
+/// (user, media, anilist_id), and a row stamped with `run_at` is added to `score_history`,
+/// so repeated runs build up the user's score history. Returns the rows written per table.
+pub fn write_scores(
+    path: &str,
+    user: &str,
+    lists: &[AnilistScores],
+    run_at: i64,
+) -> rusqlite::Result<usize> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(CREATE_TABLES)?;
+
+    let tx = conn.transaction()?;
+    let mut written = 0;
+    for table in ["scores", "score_history"] {
+        let mut insert = tx.prepare(&format!(
+            "INSERT OR REPLACE INTO {table} ({COLUMNS}) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+        ))?;
+        written = 0;
+        for list in lists {
+            for i in 0..list.anilist_id.len() {
+                insert.execute(params![
+                    user,
+                    list.media_type,
+                    list.anilist_id[i],
+                    list.list_type,
+                    list.title[i],
+                    list.user_score[i],
+                    list.global_avg_score[i],
+                    list.updated_at[i],
+                    list.status[i],
+                    list.release_year.get(i).copied().unwrap_or(0),
+                    list.notes.get(i).map(String::as_str).unwrap_or_default(),
+                    run_at,
+                ])?;
+                written += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn list(user_score: i64) -> AnilistScores {
+        AnilistScores::fixture(vec![user_score, 90], vec![80, 86])
+            .with_titles(&["Haibane Renmei", "Mushishi"])
+    }
+
+    #[test]
+    fn reruns_replace_scores_and_keep_history() {
+        let path = std::env::temp_dir().join(format!("anilist_sqlite_{}.db", process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        assert_eq!(
+            write_scores(&path, "koisland", &[list(70)], 100).unwrap(),
+            2
+        );
+        assert_eq!(
+            write_scores(&path, "koisland", &[list(75)], 200).unwrap(),
+            2
+        );
+
+        let conn = Connection::open(&path).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM scores"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM score_history"), 4);
+        assert_eq!(
+            count("SELECT user_score FROM scores WHERE anilist_id = 1"),
+            75
+        );
+        assert_eq!(
+            count("SELECT user_score FROM score_history WHERE anilist_id = 1 AND run_at = 100"),
+            70
+        );
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use crate::pearson;
+
+/// Indices of the lowest and highest `percent`% of the known `values`, with `percent`
+/// below 50. Unknown values are never outliers. The count trimmed from each end is
+/// rounded down, so small lists may lose nothing.
+pub fn outlier_indices(values: &[Option<i64>], percent: f64) -> HashSet<usize> {
+    let mut known: Vec<(usize, i64)> = values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, value)| value.map(|value| (i, value)))
+        .collect();
+    known.sort_by_key(|(_, value)| *value);
+
+    let trimmed = (known.len() as f64 * percent / 100.0).floor() as usize;
+    known[..trimmed]
+        .iter()
+        .chain(known[known.len() - trimmed..].iter())
+        .map(|(i, _)| *i)
+        .collect()
+}
+
+/// Weight of each entry updated at the `updated_at` timestamps, halving every
+/// `halflife_days` before `now`. Entries updated at or after `now` weigh 1, and entries
+/// without an update time (0) weigh nothing.
+pub fn recency_weights(updated_at: &[i64], now: i64, halflife_days: f64) -> Vec<f64> {
+    updated_at
+        .iter()
+        .map(|&updated_at| {
+            if updated_at <= 0 {
+                return 0.0;
+            }
+            let age_days = (now - updated_at).max(0) as f64 / 86_400.0;
+            0.5f64.powf(age_days / halflife_days)
+        })
+        .collect()
+}
+
+/// Conformity index of a user's `deltas`: the mean absolute difference between their
+/// normalized scores and the global averages, in points of the 100-point scale. 0 means
+/// every title was scored exactly at its average; lower is more average. None without
+/// deltas.
+pub fn conformity_index(deltas: &[i64]) -> Option<f64> {
+    if deltas.is_empty() {
+        return None;
+    }
+    Some(deltas.iter().map(|delta| delta.abs()).sum::<i64>() as f64 / deltas.len() as f64)
+}
+
+/// Sum of the `user` scores over the sum of the `global` scores of the same titles.
+/// Titles weigh by their global score, so one title barely moves it.
+pub fn aggregate_ratio(user: &[i64], global: &[i64]) -> f64 {
+    user.iter().sum::<i64>() as f64 / global.iter().sum::<i64>() as f64
+}
+
+/// Mean of each title's user score over its global score, skipping titles without a
+/// global score. Every title weighs the same, so titles with low global scores move it
+/// more than in `aggregate_ratio`. NaN without any.
+pub fn mean_of_ratios(user: &[i64], global: &[i64]) -> f64 {
+    let ratios: Vec<f64> = user
+        .iter()
+        .zip(global.iter())
+        .filter(|(_, global)| **global != 0)
+        .map(|(user, global)| *user as f64 / *global as f64)
+        .collect();
+    ratios.iter().sum::<f64>() / ratios.len() as f64
+}
+
+/// `values` linearly rescaled so their lowest maps to `target.0` and their highest to
+/// `target.1`. If they never vary, all map to the middle of `target`.
+pub fn min_max_rescale(values: &[f64], target: (f64, f64)) -> Vec<f64> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let (low, high) = target;
+    values
+        .iter()
+        .map(|value| {
+            if max > min {
+                low + (value - min) / (max - min) * (high - low)
+            } else {
+                (low + high) / 2.0
+            }
+        })
+        .collect()
+}
+
+/// Rank of each of `values`, 1 for the lowest. Tied values share the mean of the ranks
+/// they span.
+pub fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start;
+        while end + 1 < order.len() && values[order[end + 1]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        for i in &order[start..=end] {
+            ranks[*i] = rank;
+        }
+        start = end + 1;
+    }
+    ranks
+}
+
+/// Spearman rank correlation of `pairs`: the Pearson correlation of their ranks. None if
+/// fewer than two or either side never varies.
+pub fn spearman(pairs: &[(f64, f64)]) -> Option<f64> {
+    let xs: Vec<f64> = pairs.iter().map(|(x, _)| *x).collect();
+    let ys: Vec<f64> = pairs.iter().map(|(_, y)| *y).collect();
+    let ranked: Vec<(f64, f64)> = ranks(&xs).into_iter().zip(ranks(&ys)).collect();
+    pearson(&ranked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_bases_weigh_titles_differently() {
+        let user = [90, 60, 30];
+        let global = [80, 60, 20];
+
+        assert_eq!(aggregate_ratio(&user, &global), 180.0 / 160.0);
+        assert_eq!(
+            mean_of_ratios(&user, &global),
+            (90.0 / 80.0 + 1.0 + 1.5) / 3.0
+        );
+        // Titles without a global score are left out of the mean only.
+        assert_eq!(mean_of_ratios(&[90, 50], &[80, 0]), 90.0 / 80.0);
+        assert_eq!(aggregate_ratio(&[90, 50], &[80, 0]), 140.0 / 80.0);
+        assert!(mean_of_ratios(&[], &[]).is_nan());
+    }
+
+    #[test]
+    fn conformity_ignores_direction() {
+        assert_eq!(conformity_index(&[10, -10, 0, 4]), Some(6.0));
+        assert_eq!(conformity_index(&[-3, -3]), conformity_index(&[3, 3]));
+        assert_eq!(conformity_index(&[]), None);
+    }
+
+    #[test]
+    fn rescales_onto_target_range() {
+        assert_eq!(
+            min_max_rescale(&[40.0, 60.0, 90.0], (60.0, 100.0)),
+            vec![60.0, 76.0, 100.0]
+        );
+        assert_eq!(min_max_rescale(&[70.0, 70.0], (60.0, 100.0)), vec![80.0; 2]);
+    }
+
+    #[test]
+    fn both_ends_are_trimmed() {
+        let values: Vec<Option<i64>> = (1..=10).map(Some).chain([None]).collect();
+
+        let outliers = outlier_indices(&values, 10.0);
+        assert_eq!(outliers, HashSet::from([0, 9]));
+        assert!(outlier_indices(&values, 5.0).is_empty());
+        assert!(!outlier_indices(&values, 49.0).contains(&10));
+    }
+
+    #[test]
+    fn ties_share_their_mean_rank() {
+        let user = [80.0, 70.0, 70.0, 90.0, 60.0];
+        let global = [75.0, 75.0, 60.0, 85.0, 70.0];
+
+        assert_eq!(ranks(&user), vec![4.0, 2.5, 2.5, 5.0, 1.0]);
+        assert_eq!(ranks(&global), vec![3.5, 3.5, 1.0, 5.0, 2.0]);
+        let pairs: Vec<(f64, f64)> = user.into_iter().zip(global).collect();
+        assert!((spearman(&pairs).unwrap() - 29.0 / 38.0).abs() < 1e-9);
+        assert_eq!(spearman(&pairs[..1]), None);
+    }
+
+    #[test]
+    fn weight_halves_every_halflife() {
+        let now = 100 * 86_400;
+        let weights = recency_weights(&[now, now - 30 * 86_400, now - 60 * 86_400, 0], now, 30.0);
+
+        assert_eq!(weights, vec![1.0, 0.5, 0.25, 0.0]);
+    }
+}
@@ -0,0 +1,146 @@
+use futures_util::stream::{self, Stream, StreamExt};
+use serde_json::json;
+
+use crate::client::AnilistClient;
+use crate::error::AnilistError;
+use crate::queries::QUERY_USER_MEDIA_SCORE;
+use crate::{
+    avg_score_query, checked_chunk_size, is_excluded, max_chunk_size, media_lists_of,
+    parse_avg_scores, parse_entry_values, ListEntries, QueryOptions, ScoreEntry, DEFAULT_LISTS,
+};
+
+/// Entries of the lists `options` select, filtered like `get_anilist_scores` does.
+async fn fetch_entries(
+    client: &AnilistClient,
+    username: &str,
+    media: &str,
+    options: &QueryOptions,
+) -> Result<Vec<ListEntries>, AnilistError> {
+    let res = client
+        .query(&json!({
+            "query": QUERY_USER_MEDIA_SCORE,
+            "variables": {"username": username, "media": media, "notes": options.include_notes}
+        }))
+        .await?;
+
+    let mut lists = vec![];
+    for list in media_lists_of(&res)?.iter() {
+        let wanted = if options.statuses.is_empty() {
+            DEFAULT_LISTS.contains(&list.name.as_str())
+        } else {
+            !list.is_custom_list
+        } && !is_excluded(&list.name, &options.exclude_lists);
+        if !wanted {
+            continue;
+        }
+        let mut entries = parse_entry_values(list, options.score_format);
+        if let Some(warning) = entries.handle_invalid_scores(options.on_invalid_score, &list.name) {
+            eprintln!("{warning}");
+        }
+        if !options.statuses.is_empty() {
+            entries.with_status(&options.statuses);
+        }
+        if options.exclude_private {
+            entries.exclude_private();
+        }
+        if let Some(limit) = options.limit {
+            entries.truncate(limit);
+        }
+        lists.push(entries);
+    }
+    Ok(lists)
+}
+
+/// Scored entries of the same lists as `get_anilist_scores`, yielded as each chunk of
+/// global scores arrives instead of collected once every list is done. Sampling,
+/// incremental runs and the other whole-run options are not applied.
+///
+/// Nothing is queried until the stream is polled, and each chunk's query is only sent
+/// once the entries of the previous one were consumed, so a slow consumer slows the
+/// requests down. A failed chunk yields a single `Err` and the stream moves on to the
+/// next chunk.
+///
+/// Dropping the stream cancels it: the request in flight, if any, is abandoned and no
+/// further requests are sent. Entries already yielded are unaffected.
+pub fn stream_scores<'a>(
+    client: &'a AnilistClient,
+    username: &'a str,
+    media: &'a str,
+    options: &'a QueryOptions,
+) -> impl Stream<Item = Result<ScoreEntry, AnilistError>> + 'a {
+    let chunk_size = checked_chunk_size(
+        options.chunk_size.unwrap_or_else(|| max_chunk_size(false)),
+        false,
+    );
+
+    stream::once(fetch_entries(client, username, media, options)).flat_map(move |lists| {
+        let chunks: Vec<ListEntries> = match lists {
+            Ok(lists) => lists
+                .iter()
+                .flat_map(|entries| entries.chunks(chunk_size))
+                .collect(),
+            Err(err) => return stream::iter(vec![Err(err)]).left_stream(),
+        };
+        stream::iter(chunks)
+            .then(move |chunk| async move {
+                let res = client
+                    .query(&avg_score_query(
+                        media,
+                        &chunk.anilist_id,
+                        options.basis,
+                        false,
+                    ))
+                    .await?;
+                let details = parse_avg_scores(
+                    AnilistError::require_path(&res, &["data"])?,
+                    &chunk.anilist_id,
+                    options.basis,
+                )?;
+                Ok((0..chunk.len())
+                    .map(|i| {
+                        let id = chunk.anilist_id[i];
+                        let details = details.get(&id).cloned().unwrap_or_default();
+                        ScoreEntry {
+                            anilist_id: id,
+                            title: details.title.preferred(options.title_lang),
+                            user_score: chunk.user_score[i],
+                            global_avg_score: details.global_score,
+                            updated_at: chunk.updated_at[i],
+                            status: chunk.status[i].clone(),
+                        }
+                    })
+                    .collect::<Vec<ScoreEntry>>())
+            })
+            .flat_map(|entries: Result<Vec<ScoreEntry>, AnilistError>| {
+                stream::iter(match entries {
+                    Ok(entries) => entries.into_iter().map(Ok).collect(),
+                    Err(err) => vec![Err(err)],
+                })
+            })
+            .boxed_local()
+            .right_stream()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RetryPolicy;
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn failed_list_query_ends_stream() {
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/", listener.local_addr().unwrap())
+        };
+        let client = AnilistClient::new(None, RetryPolicy::default()).with_url(&closed);
+        let options = QueryOptions::default();
+
+        let entries: Vec<_> = stream_scores(&client, "koisland", "ANIME", &options)
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(entries[0], Err(AnilistError::Request(_))));
+    }
+}
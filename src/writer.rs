@@ -0,0 +1,965 @@
+use polars::prelude::{
+    AnyValue, CsvWriter, DataFrame, DataType, NamedFrom, Schema, SerWriter, Series,
+};
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+
+use crate::{format_float, AnilistScores, RatioBasis, DEFAULT_PRECISION};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Every column a scores DataFrame can have, in default order.
+pub const COLUMNS: &[&str] = &[
+    "list_type",
+    "anilist_id",
+    "title",
+    "user_score",
+    "global_avg_score",
+    "updated_at",
+    "status",
+    "release_year",
+    "airing_status",
+    "notes",
+    "progress",
+    "completion_pct",
+    "delta",
+    "url",
+];
+
+/// When csv/tsv fields are wrapped in quotes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// Only fields containing the delimiter, a quote or a newline.
+    #[default]
+    Necessary,
+    /// Every field, header included, for strict parsers.
+    Always,
+}
+
+impl QuoteStyle {
+    pub fn from_flag(value: &str) -> Option<QuoteStyle> {
+        match value.to_lowercase().as_str() {
+            "necessary" => Some(QuoteStyle::Necessary),
+            "always" => Some(QuoteStyle::Always),
+            _ => None,
+        }
+    }
+}
+
+/// Scale the score columns are written on. Only changes the output: ratios and deltas
+/// are still computed on the 100-point scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayScale {
+    /// Scores divided by 10, keeping one decimal.
+    Ten,
+    #[default]
+    Hundred,
+}
+
+impl DisplayScale {
+    pub fn from_flag(value: &str) -> Option<DisplayScale> {
+        match value {
+            "10" => Some(DisplayScale::Ten),
+            "100" => Some(DisplayScale::Hundred),
+            _ => None,
+        }
+    }
+
+    /// `score`, on the 100-point scale, as written on this scale, to the nearest integer
+    /// if `round`.
+    fn display(&self, score: i64, round: bool) -> String {
+        match self {
+            DisplayScale::Ten if round => format!("{}", (score as f64 / 10.0).round()),
+            DisplayScale::Ten => format!("{:.1}", score as f64 / 10.0),
+            DisplayScale::Hundred => score.to_string(),
+        }
+    }
+}
+
+/// Value of `--format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Tsv,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl OutputFormat {
+    pub fn from_flag(value: &str) -> Option<OutputFormat> {
+        match value.to_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "json" => Some(OutputFormat::Json),
+            "markdown" | "md" => Some(OutputFormat::Markdown),
+            "html" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Output format for a list of scores. Adding a format is a new impl plus an
+/// `OutputFormat` variant mapped to it in `writer_for_format`.
+pub trait ScoreWriter {
+    /// File extension used for files written in this format.
+    fn extension(&self) -> &'static str;
+
+    fn write(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()>;
+
+    /// Write `scores` to follow rows already written, e.g. without a header. Same as
+    /// `write` for formats that can't be appended to.
+    fn write_rows(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        self.write(scores, dest)
+    }
+}
+
+/// Post-processing applied to the scores DataFrame before any format writes it.
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    /// Drop columns whose values are all zero or null.
+    pub compact: bool,
+    /// Add a `notes` column with each entry's notes.
+    pub include_notes: bool,
+    /// Add an `airing_status` column with the `MediaStatus` of each title.
+    pub include_airing_status: bool,
+    /// Add `progress` and `completion_pct` columns with how far each title was watched or
+    /// read.
+    pub include_progress: bool,
+    /// Add a `delta` column of `user_score - global_avg_score`.
+    pub include_delta: bool,
+    /// Add a `url` column linking to each title's Anilist page.
+    pub include_url: bool,
+    /// Start CSV output with a UTF-8 byte order mark so Excel detects the encoding.
+    pub utf8_bom: bool,
+    /// Keep the `global_avg_score` and `release_year` columns. Unset when global scores
+    /// were not fetched.
+    pub include_global: bool,
+    /// Columns to move to the front, in this order. The rest follow in default order.
+    pub columns_order: Vec<String>,
+    /// When csv/tsv fields are quoted.
+    pub quote_style: QuoteStyle,
+    /// Scale of the `user_score` and `global_avg_score` columns.
+    pub display_scale: DisplayScale,
+    /// Round those columns to integers once on `display_scale`. Display only.
+    pub round_scores: bool,
+    /// How the Ratio of the markdown and html summaries is computed.
+    pub ratio_basis: RatioBasis,
+    /// Decimal places of the means and ratios of the markdown and html summaries.
+    pub precision: usize,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        WriterOptions {
+            compact: false,
+            include_notes: false,
+            include_progress: false,
+            include_airing_status: false,
+            include_delta: false,
+            include_url: false,
+            utf8_bom: false,
+            include_global: true,
+            columns_order: vec![],
+            quote_style: QuoteStyle::default(),
+            display_scale: DisplayScale::default(),
+            round_scores: false,
+            ratio_basis: RatioBasis::default(),
+            precision: DEFAULT_PRECISION,
+        }
+    }
+}
+
+impl WriterOptions {
+    fn dataframe(&self, scores: &AnilistScores) -> io::Result<DataFrame> {
+        let mut df = scores.as_dataframe().map_err(io::Error::other)?;
+        if self.display_scale == DisplayScale::Ten {
+            // User scores are brought onto the 100-point scale first.
+            let scale = scores.user_score_scale();
+            let user_scores: Vec<i64> = scores.user_score.iter().map(|s| s * scale).collect();
+            for (column, values) in [
+                ("user_score", &user_scores),
+                ("global_avg_score", &scores.global_avg_score),
+            ] {
+                let values: Vec<f64> = values.iter().map(|s| *s as f64 / 10.0).collect();
+                let series = if self.round_scores {
+                    let rounded: Vec<i64> = values.iter().map(|s| s.round() as i64).collect();
+                    Series::new(column, rounded)
+                } else {
+                    Series::new(column, values)
+                };
+                df.with_column(series)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+            }
+        }
+        if !self.include_global {
+            // Release years come with the global scores.
+            for column in ["global_avg_score", "release_year"] {
+                df = df
+                    .drop(column)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+            }
+        }
+        if self.include_airing_status {
+            let airing_status: Vec<&str> = (0..scores.anilist_id.len())
+                .map(|i| {
+                    scores
+                        .airing_status
+                        .get(i)
+                        .map(String::as_str)
+                        .unwrap_or_default()
+                })
+                .collect();
+            df.with_column(Series::new("airing_status", airing_status))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self.include_notes {
+            df.with_column(Series::new("notes", scores.notes.clone()))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self.include_progress {
+            let progress: Vec<i64> = (0..scores.anilist_id.len())
+                .map(|i| scores.progress.get(i).copied().unwrap_or(0))
+                .collect();
+            df.with_column(Series::new("progress", progress))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+            df.with_column(Series::new("completion_pct", scores.completion_pct()))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self.include_delta {
+            df.with_column(Series::new("delta", scores.deltas()))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self.include_url {
+            df.with_column(Series::new("url", scores.urls()))
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self.compact {
+            df = drop_empty_columns(&df);
+        }
+        if !self.columns_order.is_empty() {
+            df = reorder_columns(&df, &self.columns_order)
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        Ok(df)
+    }
+
+    /// Column names and dtypes of the DataFrame written with these options, built from
+    /// an empty list so it can't drift from `dataframe`. Columns `compact` would drop
+    /// depend on the data, so they are all kept.
+    pub fn schema(&self) -> io::Result<Schema> {
+        let options = WriterOptions {
+            compact: false,
+            ..self.clone()
+        };
+        Ok(options.dataframe(&AnilistScores::default())?.schema())
+    }
+}
+
+fn is_empty_column(series: &Series) -> bool {
+    if series.null_count() == series.len() {
+        return true;
+    }
+    if !series.dtype().is_numeric() {
+        return false;
+    }
+    series
+        .cast(&DataType::Float64)
+        .ok()
+        .and_then(|values| {
+            values
+                .f64()
+                .ok()
+                .map(|values| values.into_iter().all(|value| value.unwrap_or(0.0) == 0.0))
+        })
+        .unwrap_or(false)
+}
+
+/// Remove columns whose values are all zero or null.
+pub fn drop_empty_columns(df: &DataFrame) -> DataFrame {
+    let columns: Vec<Series> = df
+        .get_columns()
+        .iter()
+        .filter(|series| !is_empty_column(series))
+        .cloned()
+        .collect();
+    DataFrame::new(columns).unwrap_or_else(|_| df.clone())
+}
+
+/// Validate a comma-separated `--columns-order` value against `COLUMNS`.
+pub fn parse_columns_order(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if COLUMNS.contains(&name) {
+                Ok(name.to_string())
+            } else {
+                Err(format!(
+                    "Unknown column '{name}'. Expected one of {}.",
+                    COLUMNS.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Move the columns of `order` present in `df` to the front, keeping the rest in their
+/// current order.
+pub fn reorder_columns(df: &DataFrame, order: &[String]) -> polars::error::Result<DataFrame> {
+    let names = df.get_column_names();
+    let columns: Vec<&str> = order
+        .iter()
+        .map(|name| name.as_str())
+        .filter(|name| names.contains(name))
+        .chain(
+            names
+                .iter()
+                .copied()
+                .filter(|name| !order.iter().any(|ordered| ordered == name)),
+        )
+        .collect();
+    df.select(columns)
+}
+
+/// `value` wrapped in quotes, with its own quotes doubled.
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Write `df` with every field quoted, which polars' `CsvWriter` can't do. Nulls are
+/// written as empty quoted fields.
+fn write_quoted_csv(
+    df: &DataFrame,
+    delimiter: u8,
+    header: bool,
+    dest: &mut dyn Write,
+) -> io::Result<()> {
+    let delimiter = (delimiter as char).to_string();
+    if header {
+        let names: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .map(|name| quoted(name))
+            .collect();
+        writeln!(dest, "{}", names.join(&delimiter))?;
+    }
+    for i in 0..df.height() {
+        let row: Vec<String> = df
+            .get_columns()
+            .iter()
+            .map(|series| match series.get(i) {
+                AnyValue::Null => quoted(""),
+                AnyValue::Utf8(value) => quoted(value),
+                other => quoted(&other.to_string()),
+            })
+            .collect();
+        writeln!(dest, "{}", row.join(&delimiter))?;
+    }
+    Ok(())
+}
+
+fn any_value_to_json(value: AnyValue) -> Value {
+    match value {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(value) => json!(value),
+        AnyValue::Utf8(value) => json!(value),
+        AnyValue::Int32(value) => json!(value),
+        AnyValue::Int64(value) => json!(value),
+        AnyValue::UInt32(value) => json!(value),
+        AnyValue::UInt64(value) => json!(value),
+        AnyValue::Float32(value) => json!(value),
+        AnyValue::Float64(value) => json!(value),
+        other => json!(other.to_string()),
+    }
+}
+
+pub struct CsvScoreWriter {
+    pub delimiter: u8,
+    pub options: WriterOptions,
+}
+
+impl ScoreWriter for CsvScoreWriter {
+    fn extension(&self) -> &'static str {
+        if self.delimiter == b'\t' {
+            "tsv"
+        } else {
+            "csv"
+        }
+    }
+
+    fn write(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        if self.options.utf8_bom {
+            dest.write_all(UTF8_BOM)?;
+        }
+        self.write_csv(scores, true, dest)
+    }
+
+    fn write_rows(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        self.write_csv(scores, false, dest)
+    }
+}
+
+impl CsvScoreWriter {
+    fn write_csv(
+        &self,
+        scores: &AnilistScores,
+        header: bool,
+        dest: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut df = self.options.dataframe(scores)?;
+        if self.options.quote_style == QuoteStyle::Always {
+            return write_quoted_csv(&df, self.delimiter, header, dest);
+        }
+        CsvWriter::new(dest)
+            .has_header(header)
+            .with_delimiter(self.delimiter)
+            .finish(&mut df)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+}
+
+/// Rows as json objects. Each also holds the genres, tags and airing status fetched for
+/// its title, so `--incremental` can reuse them.
+pub struct JsonScoreWriter {
+    pub options: WriterOptions,
+}
+
+impl ScoreWriter for JsonScoreWriter {
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn write(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        let df = self.options.dataframe(scores)?;
+        let rows: Vec<Value> = (0..df.height())
+            .map(|i| {
+                let mut row: Map<String, Value> = df
+                    .get_columns()
+                    .iter()
+                    .map(|series| (series.name().to_string(), any_value_to_json(series.get(i))))
+                    .collect();
+                if let Some(genres) = scores.genres.get(i) {
+                    row.insert("genres".to_string(), json!(genres));
+                }
+                if let Some(tags) = scores.tags.get(i) {
+                    let tags: Vec<Value> = tags
+                        .iter()
+                        .map(|(name, rank)| json!({"name": name, "rank": rank}))
+                        .collect();
+                    row.insert("tags".to_string(), json!(tags));
+                }
+                if let Some(airing_status) = scores.airing_status.get(i) {
+                    row.entry("airing_status")
+                        .or_insert_with(|| json!(airing_status));
+                }
+                Value::Object(row)
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut *dest, &rows)?;
+        writeln!(dest)
+    }
+}
+
+/// Titles listed in the most divergent table of the markdown format.
+const MARKDOWN_DIVERGENT: usize = 10;
+
+/// `value` safe to put in a markdown table cell.
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+fn write_markdown_table(
+    dest: &mut dyn Write,
+    header: &[&str],
+    rows: &[Vec<String>],
+) -> io::Result<()> {
+    writeln!(dest, "| {} |", header.join(" | "))?;
+    let separator: Vec<&str> = header.iter().map(|_| "---").collect();
+    writeln!(dest, "| {} |", separator.join(" | "))?;
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+        writeln!(dest, "| {} |", cells.join(" | "))?;
+    }
+    Ok(())
+}
+
+/// GitHub-flavored markdown for sharing results: a summary of the list and its most
+/// divergent titles as tables, rather than every entry.
+pub struct MarkdownScoreWriter {
+    pub options: WriterOptions,
+}
+
+impl ScoreWriter for MarkdownScoreWriter {
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn write(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        writeln!(dest, "## {}\n", markdown_cell(&scores.list_type))?;
+        let titles = scores.anilist_id.len().to_string();
+        let mean_user = format_float(scores.mean_user_score(), self.options.precision, false);
+        if !self.options.include_global {
+            write_markdown_table(
+                dest,
+                &["Titles", "Mean user score"],
+                &[vec![titles, mean_user]],
+            )?;
+            return writeln!(dest);
+        }
+        write_markdown_table(
+            dest,
+            &[
+                "Titles",
+                "Mean user score",
+                "Mean global score",
+                "Ratio",
+                "Mean delta",
+            ],
+            &[vec![
+                titles,
+                mean_user,
+                format_float(scores.mean_global_score(), self.options.precision, false),
+                format_float(
+                    scores.ratio_on(self.options.ratio_basis),
+                    self.options.precision,
+                    false,
+                ),
+                format_float(scores.mean_delta(), self.options.precision, true),
+            ]],
+        )?;
+
+        let (display_scale, round) = (self.options.display_scale, self.options.round_scores);
+        // Scores are shown as entered unless rescaled.
+        let user_scale = match display_scale {
+            DisplayScale::Ten => scores.user_score_scale(),
+            DisplayScale::Hundred => 1,
+        };
+        let divergent: Vec<Vec<String>> = scores
+            .most_divergent(MARKDOWN_DIVERGENT)
+            .into_iter()
+            .map(|(entry, delta)| {
+                vec![
+                    entry.title,
+                    display_scale.display(entry.user_score * user_scale, round),
+                    display_scale.display(entry.global_avg_score, round),
+                    format!("{delta:+}"),
+                ]
+            })
+            .collect();
+        if !divergent.is_empty() {
+            writeln!(dest, "\n### Most divergent\n")?;
+            write_markdown_table(
+                dest,
+                &["Title", "User score", "Global score", "Delta"],
+                &divergent,
+            )?;
+        }
+        writeln!(dest)
+    }
+}
+
+/// `value` safe to put in HTML text or a quoted attribute.
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Titles with a normalized user score in each tenth of the 100-point scale, 90-100 in
+/// the last. Unscored titles are left out.
+fn score_histogram(scores: &AnilistScores) -> [usize; 10] {
+    let scale = scores.user_score_scale();
+    let mut counts = [0; 10];
+    for score in scores.user_score.iter().filter(|score| **score > 0) {
+        counts[((score * scale / 10) as usize).min(9)] += 1;
+    }
+    counts
+}
+
+/// Height in pixels of the tallest bar of the score distribution chart.
+const HISTOGRAM_HEIGHT: usize = 120;
+const HISTOGRAM_BAR_WIDTH: usize = 36;
+
+/// Inline SVG bar chart of `counts`, one bar per tenth of the scale.
+fn histogram_svg(counts: &[usize; 10]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let width = counts.len() * (HISTOGRAM_BAR_WIDTH + 4);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{}\" role=\"img\" aria-label=\"Score distribution\">\n",
+        HISTOGRAM_HEIGHT + 20
+    );
+    for (i, count) in counts.iter().enumerate() {
+        let height = count * HISTOGRAM_HEIGHT / max;
+        let x = i * (HISTOGRAM_BAR_WIDTH + 4);
+        svg.push_str(&format!(
+            "<rect x=\"{x}\" y=\"{}\" width=\"{HISTOGRAM_BAR_WIDTH}\" height=\"{height}\" fill=\"#3db4f2\"><title>{}-{}: {count}</title></rect>\n",
+            HISTOGRAM_HEIGHT - height,
+            i * 10,
+            if i == 9 { 100 } else { i * 10 + 9 }
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"11\" text-anchor=\"middle\">{}</text>\n",
+            x + HISTOGRAM_BAR_WIDTH / 2,
+            HISTOGRAM_HEIGHT + 14,
+            i * 10
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn write_html_table(
+    dest: &mut dyn Write,
+    header: &[String],
+    rows: &[Vec<String>],
+) -> io::Result<()> {
+    writeln!(dest, "<table>")?;
+    let header: Vec<String> = header
+        .iter()
+        .map(|name| format!("<th>{}</th>", html_escape(name)))
+        .collect();
+    writeln!(dest, "<tr>{}</tr>", header.concat())?;
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|cell| format!("<td>{}</td>", html_escape(cell)))
+            .collect();
+        writeln!(dest, "<tr>{}</tr>", cells.concat())?;
+    }
+    writeln!(dest, "</table>")
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f3f3f3; }";
+
+/// A single HTML page per list, with no external resources: a summary table, an SVG chart
+/// of the user's score distribution and a table of every entry.
+pub struct HtmlScoreWriter {
+    pub options: WriterOptions,
+}
+
+impl ScoreWriter for HtmlScoreWriter {
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn write(&self, scores: &AnilistScores, dest: &mut dyn Write) -> io::Result<()> {
+        let list_type = html_escape(&scores.list_type);
+        writeln!(dest, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>")?;
+        writeln!(dest, "<meta charset=\"utf-8\">\n<title>{list_type}</title>")?;
+        writeln!(dest, "<style>\n{HTML_STYLE}\n</style>\n</head>\n<body>")?;
+        writeln!(dest, "<h1>{list_type}</h1>")?;
+
+        let mut header = vec!["Titles".to_string(), "Mean user score".to_string()];
+        let mut summary = vec![
+            scores.anilist_id.len().to_string(),
+            format_float(scores.mean_user_score(), self.options.precision, false),
+        ];
+        if self.options.include_global {
+            header.extend(["Mean global score", "Ratio", "Mean delta"].map(String::from));
+            summary.extend([
+                format_float(scores.mean_global_score(), self.options.precision, false),
+                format_float(
+                    scores.ratio_on(self.options.ratio_basis),
+                    self.options.precision,
+                    false,
+                ),
+                format_float(scores.mean_delta(), self.options.precision, true),
+            ]);
+        }
+        write_html_table(dest, &header, &[summary])?;
+
+        writeln!(dest, "<h2>Score distribution</h2>")?;
+        writeln!(dest, "{}", histogram_svg(&score_histogram(scores)))?;
+
+        writeln!(dest, "<h2>Titles</h2>")?;
+        let df = self.options.dataframe(scores)?;
+        let header: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = (0..df.height())
+            .map(|i| {
+                df.get_columns()
+                    .iter()
+                    .map(|series| match series.get(i) {
+                        AnyValue::Null => String::new(),
+                        AnyValue::Utf8(value) => value.to_string(),
+                        other => other.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        write_html_table(dest, &header, &rows)?;
+        writeln!(dest, "</body>\n</html>")
+    }
+}
+
+/// Select the writer for a `--format` value.
+pub fn writer_for_format(format: OutputFormat, options: WriterOptions) -> Box<dyn ScoreWriter> {
+    match format {
+        OutputFormat::Csv => Box::new(CsvScoreWriter {
+            delimiter: b',',
+            options,
+        }),
+        OutputFormat::Tsv => Box::new(CsvScoreWriter {
+            delimiter: b'\t',
+            options,
+        }),
+        OutputFormat::Json => Box::new(JsonScoreWriter { options }),
+        OutputFormat::Markdown => Box::new(MarkdownScoreWriter { options }),
+        OutputFormat::Html => Box::new(HtmlScoreWriter { options }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::{df, CsvReader, SerReader};
+
+    #[test]
+    fn unspecified_columns_keep_default_order() {
+        let df = df!(
+            "list_type" => ["Completed"],
+            "anilist_id" => [457],
+            "title" => ["Mushishi"],
+            "user_score" => [90]
+        )
+        .unwrap();
+        let order = parse_columns_order("user_score,title,url").unwrap();
+
+        let reordered = reorder_columns(&df, &order).unwrap();
+        assert_eq!(
+            reordered.get_column_names(),
+            vec!["user_score", "title", "list_type", "anilist_id"]
+        );
+    }
+
+    #[test]
+    fn schema_follows_options() {
+        let options = WriterOptions {
+            include_url: true,
+            include_global: false,
+            columns_order: vec!["url".to_string()],
+            ..Default::default()
+        };
+
+        let schema = options.schema().unwrap();
+        let names: Vec<&str> = schema.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names[0], "url");
+        assert!(!names.contains(&"global_avg_score"));
+        assert_eq!(schema.get("user_score"), Some(&DataType::Int64));
+    }
+
+    #[test]
+    fn notes_are_quoted() {
+        let scores = AnilistScores {
+            notes: vec!["Calm, \"slow\"".to_string()],
+            ..AnilistScores::fixture(vec![90], vec![86]).with_titles(&["Mushishi"])
+        };
+        let writer = CsvScoreWriter {
+            delimiter: b',',
+            options: WriterOptions {
+                include_notes: true,
+                ..Default::default()
+            },
+        };
+
+        let mut out = vec![];
+        writer.write(&scores, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.lines().next().unwrap().ends_with(",notes"));
+        assert!(csv.contains(r#","Calm, ""slow""""#));
+    }
+
+    #[test]
+    fn titles_with_commas_round_trip() {
+        let scores = AnilistScores::fixture(vec![90, 85], vec![83, 86])
+            .with_titles(&["Bakemonogatari, Part 1", "Mushishi"]);
+
+        for quote_style in [QuoteStyle::Necessary, QuoteStyle::Always] {
+            let writer = CsvScoreWriter {
+                delimiter: b',',
+                options: WriterOptions {
+                    quote_style,
+                    ..Default::default()
+                },
+            };
+            let mut out = vec![];
+            writer.write(&scores, &mut out).unwrap();
+
+            let df = CsvReader::new(io::Cursor::new(out))
+                .has_header(true)
+                .finish()
+                .unwrap();
+            let titles: Vec<Option<&str>> = df
+                .column("title")
+                .unwrap()
+                .utf8()
+                .unwrap()
+                .into_iter()
+                .collect();
+            assert_eq!(
+                titles,
+                vec![Some("Bakemonogatari, Part 1"), Some("Mushishi")]
+            );
+            assert_eq!(df.column("user_score").unwrap().get(1), AnyValue::Int64(85));
+        }
+    }
+
+    #[test]
+    fn completion_of_ongoing_series_is_null() {
+        let scores = AnilistScores {
+            list_type: "Watching".to_string(),
+            status: vec!["CURRENT".to_string(); 2],
+            progress: vec![1000, 13],
+            length: vec![None, Some(26)],
+            ..AnilistScores::fixture(vec![80, 90], vec![88, 86])
+                .with_titles(&["One Piece", "Mushishi"])
+        };
+        let options = WriterOptions {
+            include_progress: true,
+            ..Default::default()
+        };
+
+        let df = options.dataframe(&scores).unwrap();
+        assert_eq!(df.column("progress").unwrap().get(0), AnyValue::Int64(1000));
+        assert_eq!(df.column("completion_pct").unwrap().get(0), AnyValue::Null);
+        assert_eq!(
+            df.column("completion_pct").unwrap().get(1),
+            AnyValue::Float64(50.0)
+        );
+    }
+
+    #[test]
+    fn ten_point_display_scale_divides_scores() {
+        let scores = AnilistScores::fixture(vec![85, 90], vec![86, 81])
+            .with_titles(&["Mushishi", "Haibane Renmei"]);
+        let options = WriterOptions {
+            display_scale: DisplayScale::Ten,
+            ..Default::default()
+        };
+
+        let df = options.dataframe(&scores).unwrap();
+        assert_eq!(
+            df.column("user_score").unwrap().get(0),
+            AnyValue::Float64(8.5)
+        );
+        assert_eq!(
+            df.column("global_avg_score").unwrap().get(1),
+            AnyValue::Float64(8.1)
+        );
+    }
+
+    #[test]
+    fn rounded_scores_are_integers_on_the_display_scale() {
+        let scores = AnilistScores::fixture(vec![73, 90], vec![86, 81])
+            .with_titles(&["Mushishi", "Haibane Renmei"]);
+        let options = WriterOptions {
+            display_scale: DisplayScale::Ten,
+            round_scores: true,
+            ..Default::default()
+        };
+
+        let df = options.dataframe(&scores).unwrap();
+        assert_eq!(df.column("user_score").unwrap().get(0), AnyValue::Int64(7));
+        assert_eq!(
+            df.column("global_avg_score").unwrap().get(0),
+            AnyValue::Int64(9)
+        );
+        assert_eq!(DisplayScale::Ten.display(73, true), "7");
+        assert_eq!(DisplayScale::Ten.display(73, false), "7.3");
+        assert_eq!(DisplayScale::Hundred.display(73, true), "73");
+        // Only the output is rounded.
+        assert_eq!(scores.user_score, vec![73, 90]);
+    }
+
+    #[test]
+    fn always_quotes_every_field() {
+        let df = df!("title" => ["Mushishi"], "user_score" => [85]).unwrap();
+        let mut out = vec![];
+        write_quoted_csv(&df, b'\t', true, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\"title\"\t\"user_score\"\n\"Mushishi\"\t\"85\"\n"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_pipes_in_titles() {
+        let scores = AnilistScores::fixture(vec![60, 90], vec![80, 86])
+            .with_titles(&["Fate/stay night | UBW", "Mushishi"]);
+        let writer = MarkdownScoreWriter {
+            options: WriterOptions::default(),
+        };
+        let mut out = vec![];
+        writer.write(&scores, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("## Completed\n\n| Titles | Mean user score |"));
+        assert!(out.contains("| --- | --- | --- | --- | --- |"));
+        assert!(out.contains("| Fate/stay night \\| UBW | 60 | 80 | -20 |"));
+        assert!(out.find("UBW").unwrap() < out.find("Mushishi").unwrap());
+    }
+
+    #[test]
+    fn summary_ratio_follows_the_ratio_basis() {
+        let scores = AnilistScores::fixture(vec![90, 40], vec![60, 80])
+            .with_titles(&["Mushishi", "Haibane Renmei"]);
+        let summary = |ratio_basis| {
+            let mut out = vec![];
+            MarkdownScoreWriter {
+                options: WriterOptions {
+                    ratio_basis,
+                    ..Default::default()
+                },
+            }
+            .write(&scores, &mut out)
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(summary(RatioBasis::Aggregate).contains("| 0.929 |"));
+        assert!(summary(RatioBasis::Mean).contains("| 1.000 |"));
+    }
+
+    #[test]
+    fn html_report_is_escaped_and_self_contained() {
+        let scores = AnilistScores::fixture(vec![60, 95, 100], vec![80, 86, 86]).with_titles(&[
+            "<script>alert(1)</script>",
+            "Tom & Jerry",
+            "Mushishi",
+        ]);
+        assert_eq!(score_histogram(&scores), [0, 0, 0, 0, 0, 0, 1, 0, 0, 2]);
+
+        let writer = HtmlScoreWriter {
+            options: WriterOptions::default(),
+        };
+        let mut out = vec![];
+        writer.write(&scores, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("<!DOCTYPE html>"));
+        assert!(out.trim_end().ends_with("</html>"));
+        assert!(!out.contains("<script>"));
+        assert!(out.contains("<td>&lt;script&gt;alert(1)&lt;/script&gt;</td>"));
+        assert!(out.contains("<td>Tom &amp; Jerry</td>"));
+        assert_eq!(out.matches("<rect ").count(), 10);
+        assert!(!out.contains("src=") && !out.contains("href="));
+    }
+
+    #[test]
+    fn unknown_column_is_rejected() {
+        assert!(parse_columns_order("anilist_id,score").is_err());
+    }
+}